@@ -0,0 +1,181 @@
+use sha2::{Digest,Sha256};
+use rand::Rng;
+use std::path::{Path,PathBuf};
+
+// Number of times a password is hashed. This makes guessing expensive.
+const HASH_ROUNDS: u32 = 10000;
+
+// The login credentials for a character. These are kept in a file
+// next to the character data.
+pub struct Account
+{
+	pub name: String,
+	salt: String,
+	password_hash: String
+}
+
+impl Account
+{
+	pub fn new(name: &String, password: &String) -> Account
+	{
+		let mut account = Account
+		{
+			name: name.clone(),
+			salt: String::new(),
+			password_hash: String::new()
+		};
+		account.set_password(password);
+		return account;
+	}
+
+	// Names become file names, so only letters are allowed
+	pub fn valid_name(name: &str) -> bool
+	{
+		return name.len() >= 2 && name.len() <= 20 && name.chars().all(|c| c.is_ascii_alphabetic());
+	}
+
+	pub fn set_password(&mut self, password: &String)
+	{
+		let salt: [u8;16] = rand::thread_rng().gen();
+		self.salt = Account::to_hex(&salt);
+		self.password_hash = Account::hash(&self.salt,password);
+	}
+
+	pub fn verify(&self, password: &String) -> bool
+	{
+		let attempt = Account::hash(&self.salt,password);
+		if attempt.len() != self.password_hash.len()
+		{
+			return false;
+		}
+		// Compare every byte so that the time taken reveals nothing
+		let mut difference = 0;
+		for (a,b) in attempt.bytes().zip(self.password_hash.bytes())
+		{
+			difference |= a ^ b;
+		}
+		return difference == 0;
+	}
+
+	fn to_hex(bytes: &[u8]) -> String
+	{
+		let mut result = String::new();
+		for byte in bytes.iter()
+		{
+			result += &format!("{:02x}",byte);
+		}
+		return result;
+	}
+
+	fn hash(salt: &String, password: &String) -> String
+	{
+		let mut digest = Sha256::digest((salt.clone()+password).as_bytes());
+		for _ in 1..HASH_ROUNDS
+		{
+			let mut hasher = Sha256::new();
+			hasher.update(salt.as_bytes());
+			hasher.update(digest);
+			digest = hasher.finalize();
+		}
+		return Account::to_hex(&digest);
+	}
+
+	fn file_name(directory: &str, name: &String) -> PathBuf
+	{
+		let mut file_name = name.clone()+&".account".to_string();
+		file_name.make_ascii_lowercase();
+		return Path::new(directory).join(file_name);
+	}
+
+	pub fn load(directory: &str, name: &String) -> Option<Account>
+	{
+		let result = csv::Reader::from_path(Account::file_name(directory,name));
+		let mut salt = None;
+		let mut password_hash = None;
+		match result
+		{
+			Ok(mut reader) =>
+				{
+					for item in reader.records()
+					{
+						match item
+						{
+							Ok(item) =>
+								{
+									let key = item.get(0).unwrap_or("");
+									let value = item.get(1).unwrap_or("");
+									match key
+									{
+										"salt" => { salt = Some(value.to_string()); },
+										"hash" => { password_hash = Some(value.to_string()); },
+										_ => { () }
+									}
+								},
+							_ => { return None; }
+						}
+					}
+				},
+			_ => { return None; }
+		}
+		if salt.is_none() || password_hash.is_none()
+		{
+			return None;
+		}
+		return Some(Account { name: name.clone(), salt: salt.unwrap(), password_hash: password_hash.unwrap() });
+	}
+
+	pub fn save(&self, directory: &str)
+	{
+		let mut wtr = csv::Writer::from_path(Account::file_name(directory,&self.name)).unwrap();
+		let _ = wtr.write_record(&["name",&self.name]).unwrap();
+		let _ = wtr.write_record(&["salt",&self.salt]).unwrap();
+		let _ = wtr.write_record(&["hash",&self.password_hash]).unwrap();
+		let _ = wtr.flush().unwrap();
+	}
+}
+
+#[cfg(test)]
+mod account_unit_test
+{
+	use super::*;
+
+	#[test]
+	fn verify_test()
+	{
+		let mut account = Account::new(&"Jim".to_string(),&"secret".to_string());
+		assert!(account.verify(&"secret".to_string()));
+		assert!(!account.verify(&"Secret".to_string()));
+		assert!(!account.verify(&"".to_string()));
+		// The same password gets a different salt and hash
+		let other = Account::new(&"Jim".to_string(),&"secret".to_string());
+		assert_ne!(account.salt,other.salt);
+		assert_ne!(account.password_hash,other.password_hash);
+		account.set_password(&"new secret".to_string());
+		assert!(!account.verify(&"secret".to_string()));
+		assert!(account.verify(&"new secret".to_string()));
+	}
+
+	#[test]
+	fn save_load_account()
+	{
+		let account = Account::new(&"AccountTest".to_string(),&"hunter2".to_string());
+		account.save(".");
+		let loaded = Account::load(".",&"AccountTest".to_string());
+		let _ = std::fs::remove_file(Account::file_name(".",&account.name));
+		assert!(loaded.is_some());
+		let loaded = loaded.unwrap();
+		assert!(loaded.verify(&"hunter2".to_string()));
+		assert!(!loaded.verify(&"hunter3".to_string()));
+		assert!(Account::load(".",&"Lord Tom".to_string()).is_none());
+	}
+
+	#[test]
+	fn valid_name_test()
+	{
+		assert!(Account::valid_name("Jim"));
+		assert!(!Account::valid_name("J"));
+		assert!(!Account::valid_name("../Jim"));
+		assert!(!Account::valid_name("Lord Tom"));
+		assert!(!Account::valid_name(""));
+	}
+}
@@ -0,0 +1,100 @@
+// Colour markup for game text. A tag such as {r} starts a colour and {x}
+// goes back to normal. Upper case tags are the bright versions. The tags
+// become ANSI codes for players who want colour and are removed for the
+// rest. Write {{ for a literal brace.
+
+fn ansi_code(tag: char) -> Option<&'static str>
+{
+	match tag
+	{
+		'x' => { return Some("\x1b[0m"); },
+		'r' => { return Some("\x1b[31m"); },
+		'g' => { return Some("\x1b[32m"); },
+		'y' => { return Some("\x1b[33m"); },
+		'b' => { return Some("\x1b[34m"); },
+		'm' => { return Some("\x1b[35m"); },
+		'c' => { return Some("\x1b[36m"); },
+		'w' => { return Some("\x1b[37m"); },
+		'R' => { return Some("\x1b[1;31m"); },
+		'G' => { return Some("\x1b[1;32m"); },
+		'Y' => { return Some("\x1b[1;33m"); },
+		'B' => { return Some("\x1b[1;34m"); },
+		'M' => { return Some("\x1b[1;35m"); },
+		'C' => { return Some("\x1b[1;36m"); },
+		'W' => { return Some("\x1b[1;37m"); },
+		_ => { return None; }
+	}
+}
+
+// Turn the markup into ANSI codes, or remove it if colour is off
+pub fn render(text: &str, enabled: bool) -> String
+{
+	let mut result = String::with_capacity(text.len());
+	let mut chars = text.chars().peekable();
+	// Is a colour still in effect?
+	let mut colored = false;
+	while let Some(c) = chars.next()
+	{
+		if c != '{'
+		{
+			result.push(c);
+			continue;
+		}
+		let tag = chars.peek().copied();
+		if tag == Some('{')
+		{
+			chars.next();
+			result.push('{');
+			continue;
+		}
+		let code = tag.and_then(ansi_code);
+		let closed = chars.clone().nth(1) == Some('}');
+		match code
+		{
+			Some(code) if closed =>
+				{
+					chars.next();
+					chars.next();
+					if enabled
+					{
+						result += code;
+						colored = tag != Some('x');
+					}
+				},
+			// Not a tag, so leave it alone
+			_ => { result.push(c); }
+		}
+	}
+	// Don't let the colour run into whatever comes next
+	if colored
+	{
+		result += ansi_code('x').unwrap();
+	}
+	return result;
+}
+
+// Protect text from the players so that its braces are shown as they are
+pub fn escape(text: &str) -> String
+{
+	return text.replace('{',"{{");
+}
+
+#[cfg(test)]
+mod color_unit_test
+{
+	use super::*;
+
+	#[test]
+	fn render_test()
+	{
+		let text = "{R}The orc slays you!{x} Ouch.";
+		assert_eq!(render(text,false),"The orc slays you! Ouch.");
+		assert_eq!(render(text,true),"\x1b[1;31mThe orc slays you!\x1b[0m Ouch.");
+		// An unclosed colour is reset at the end
+		assert_eq!(render("{g}grass",true),"\x1b[32mgrass\x1b[0m");
+		// Things that are not tags are left alone
+		assert_eq!(render("{q} {r {",false),"{q} {r {");
+		assert_eq!(render("{{r}",true),"{r}");
+		assert_eq!(render(&escape("{r}%h{x}"),true),"{r}%h{x}");
+	}
+}
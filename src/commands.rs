@@ -0,0 +1,1593 @@
+// The commands that players type. Each command is registered with its
+// name, aliases, shortest abbreviation, arguments and help, so adding a
+// command does not mean touching the game loop.
+use crate::world::*;
+use crate::object::*;
+use crate::events::*;
+use crate::account::*;
+use crate::config::*;
+use crate::items;
+use crate::color;
+use crate::prompt;
+use crate::location::LocationTypeCode;
+use crate::mobile::Mobile;
+use std::path::Path;
+use std::time::{Duration,Instant};
+
+// Everything a command can work on
+pub struct CommandContext<'a>
+{
+	// The mobile of the player running the command
+	pub uuid: usize,
+	pub world: &'a mut WorldState,
+	pub event_q: &'a mut EventList,
+	pub config: &'a Config,
+	pub commands: &'a CommandRegistry,
+	// The players who are connected
+	pub online: &'a [OnlinePlayer]
+}
+
+pub struct OnlinePlayer
+{
+	pub uuid: usize,
+	// Time since the player last typed something
+	pub idle: Duration
+}
+
+impl CommandContext<'_>
+{
+	pub fn is_admin(&mut self) -> bool
+	{
+		let position = self.world.find_mobile_location(self.uuid).unwrap();
+		let mobile = self.world.fetch_mobile(self.uuid).unwrap();
+		let admin = self.config.is_admin(&mobile.name);
+		self.world.add_mobile(mobile,position.0,position.1);
+		return admin;
+	}
+}
+
+// What a command expects after its name
+pub enum Arguments
+{
+	// Anything after the name is ignored
+	Nothing,
+	// One word that may be left out
+	Optional,
+	// One word. The text is the reply when it is missing.
+	Required(&'static str),
+	// Two words
+	Pair,
+	// The rest of the line as it was typed, if there is any
+	Text,
+	// The rest of the line, which must be there
+	RequiredText(&'static str),
+	// One word and then the rest of the line
+	WordAndText(&'static str)
+}
+
+pub trait Command
+{
+	fn name(&self) -> &str;
+	// Other words that run the command
+	fn aliases(&self) -> &[&str];
+	// Shortest prefix of the name that runs the command
+	fn abbreviation(&self) -> usize;
+	fn arguments(&self) -> &Arguments;
+	// The arguments as they are shown in the help, such as <object>
+	fn usage(&self) -> &str;
+	fn help(&self) -> &str;
+	// Hidden from everyone but the admins
+	fn admin_only(&self) -> bool;
+	// Can be used without coming out of hiding
+	fn quiet(&self) -> bool;
+	fn run(&self, args: &[String], context: &mut CommandContext) -> String;
+}
+
+// A command whose handler is a plain function
+pub struct BasicCommand
+{
+	pub name: &'static str,
+	pub aliases: &'static [&'static str],
+	pub abbreviation: usize,
+	pub arguments: Arguments,
+	pub usage: &'static str,
+	pub help: &'static str,
+	pub admin_only: bool,
+	pub quiet: bool,
+	pub handler: fn(&[String], &mut CommandContext) -> String
+}
+
+impl Command for BasicCommand
+{
+	fn name(&self) -> &str { return self.name; }
+	fn aliases(&self) -> &[&str] { return self.aliases; }
+	fn abbreviation(&self) -> usize { return self.abbreviation; }
+	fn arguments(&self) -> &Arguments { return &self.arguments; }
+	fn usage(&self) -> &str { return self.usage; }
+	fn help(&self) -> &str { return self.help; }
+	fn admin_only(&self) -> bool { return self.admin_only; }
+	fn quiet(&self) -> bool { return self.quiet; }
+
+	fn run(&self, args: &[String], context: &mut CommandContext) -> String
+	{
+		return (self.handler)(args,context);
+	}
+}
+
+// Width of the column of command names in the help
+const HELP_COLUMN: usize = 16;
+
+pub struct CommandRegistry
+{
+	commands: Vec<Box<dyn Command> >
+}
+
+impl CommandRegistry
+{
+	pub fn new() -> CommandRegistry
+	{
+		return CommandRegistry { commands: Vec::new() };
+	}
+
+	pub fn register(&mut self, command: Box<dyn Command>)
+	{
+		self.commands.push(command);
+	}
+
+	// Find the command for a word. A name or alias typed in full always
+	// wins. Otherwise the word must be the start of just one name, and at
+	// least as long as its abbreviation.
+	pub fn find(&self, word: &str, admin: bool) -> Result<&dyn Command,String>
+	{
+		let visible: Vec<&dyn Command> = self.commands.iter()
+			.map(|command| command.as_ref())
+			.filter(|command| admin || !command.admin_only())
+			.collect();
+		for command in visible.iter()
+		{
+			if command.name() == word || command.aliases().contains(&word)
+			{
+				return Ok(*command);
+			}
+		}
+		let prefixed: Vec<&dyn Command> = visible.into_iter()
+			.filter(|command| command.name().starts_with(word))
+			.collect();
+		let allowed: Vec<&dyn Command> = prefixed.iter()
+			.filter(|command| word.len() >= command.abbreviation())
+			.copied()
+			.collect();
+		if allowed.len() == 1
+		{
+			return Ok(allowed[0]);
+		}
+		let candidates = if allowed.is_empty() { prefixed } else { allowed };
+		if candidates.len() > 1
+		{
+			let names: Vec<&str> = candidates.iter().map(|command| command.name()).collect();
+			return Err("Which do you mean: ".to_string()+&names.join(", ")+"?");
+		}
+		return Err("What?".to_string());
+	}
+
+	// Run a line of input
+	pub fn dispatch(&self, line: &str, context: &mut CommandContext) -> String
+	{
+		let line = line.trim();
+		let (word, rest) = match line.split_once(char::is_whitespace)
+		{
+			Some((word,rest)) => { (word,rest.trim_start()) },
+			None => { (line,"") }
+		};
+		if word.is_empty()
+		{
+			return "What?".to_string();
+		}
+		let admin = context.is_admin();
+		let command = match self.find(&word.to_lowercase(),admin)
+		{
+			Ok(command) => { command },
+			Err(reply) => { return reply; }
+		};
+		let words: Vec<String> = rest.split_whitespace().map(|s| s.to_string()).collect();
+		let args = match command.arguments()
+		{
+			Arguments::Nothing => { Vec::new() },
+			Arguments::Optional => { words.into_iter().take(1).collect() },
+			Arguments::Required(missing) =>
+				{
+					if words.is_empty()
+					{
+						return missing.to_string();
+					}
+					words.into_iter().take(1).collect()
+				},
+			Arguments::Pair =>
+				{
+					if words.len() < 2
+					{
+						return "Usage: ".to_string()+command.name()+" "+command.usage();
+					}
+					words.into_iter().take(2).collect()
+				},
+			Arguments::Text =>
+				{
+					if rest.is_empty() { Vec::new() } else { vec![rest.to_string()] }
+				},
+			Arguments::RequiredText(missing) =>
+				{
+					if rest.is_empty()
+					{
+						return missing.to_string();
+					}
+					vec![rest.to_string()]
+				},
+			Arguments::WordAndText(missing) =>
+				{
+					match rest.split_once(char::is_whitespace)
+					{
+						Some((word,text)) => { vec![word.to_string(),text.trim_start().to_string()] },
+						None => { return missing.to_string(); }
+					}
+				}
+		};
+		// Doing anything but looking around brings you out of hiding
+		if !command.quiet() && stop_hiding(context.uuid,context.world)
+		{
+			return "You stop hiding.\n".to_string()+&command.run(&args,context);
+		}
+		return command.run(&args,context);
+	}
+
+	// One entry of the help. The name column is padded, and each line
+	// of the help text is lined up after it.
+	fn help_entry(command: &dyn Command) -> String
+	{
+		let mut left = command.name().to_string();
+		if !command.usage().is_empty()
+		{
+			left += &(" ".to_string()+command.usage());
+		}
+		let mut result = String::new();
+		let indent = " ".repeat(HELP_COLUMN);
+		if left.len() < HELP_COLUMN
+		{
+			result += &format!("{:width$}",left,width = HELP_COLUMN);
+		}
+		else
+		{
+			result += &(left+"\n"+&indent);
+		}
+		result += &command.help().replace('\n',&("\n".to_string()+&indent));
+		result.push('\n');
+		return result;
+	}
+
+	// The list of commands, or the details of one of them
+	pub fn help(&self, topic: Option<&String>, admin: bool) -> String
+	{
+		match topic
+		{
+			Some(topic) =>
+				{
+					let command = match self.find(&topic.to_lowercase(),admin)
+					{
+						Ok(command) => { command },
+						Err(_e) => { return "There is no help on that.".to_string(); }
+					};
+					let mut result = CommandRegistry::help_entry(command);
+					if !command.aliases().is_empty()
+					{
+						result += &("Also: ".to_string()+&command.aliases().join(", ")+"\n");
+					}
+					if command.abbreviation() < command.name().len()
+					{
+						result += &("Shortest: ".to_string()+&command.name()[..command.abbreviation()]+"\n");
+					}
+					return result;
+				},
+			None =>
+				{
+					let mut result = "Welcome to the Procedurally Generated MUD! Here you can:\n\n".to_string();
+					for command in self.commands.iter()
+					{
+						if admin || !command.admin_only()
+						{
+							result += &CommandRegistry::help_entry(command.as_ref());
+						}
+					}
+					result += "\nCommands can be shortened, as in loo for look. Type help <command> for more.\n";
+					return result;
+				}
+		}
+	}
+}
+
+// "all" or "all.<keyword>" means every item that matches
+fn all_keyword(target: &String) -> Option<String>
+{
+	if target == "all"
+	{
+		return Some(String::new());
+	}
+	return target.strip_prefix("all.").map(|keyword| keyword.to_string());
+}
+
+fn get_item(uuid: usize, world: &mut WorldState, target: &String) -> String
+{
+	match all_keyword(target)
+	{
+		Some(keyword) => { return get_all(uuid,world,&keyword); },
+		None => { () }
+	}
+	let mut result = "Got it!".to_string();
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let item = world.fetch_item_by_name(position.0,position.1,target,uuid);
+	if item.is_some()
+	{
+		let item = item.unwrap();
+		if mobile.has_room_for_item()
+		{
+			mobile.add_item(item,true);
+		}
+		else
+		{
+			result = "You don't have space for that!".to_string();
+			world.add_item(position.0,position.1,item);
+		}
+	}
+	else
+	{
+		result = "Get what?".to_string();
+	}
+	world.add_mobile(mobile,position.0,position.1);
+	return result;
+}
+
+// Pick up everything here that matches the keyword and fits
+fn get_all(uuid: usize, world: &mut WorldState, keyword: &String) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let mut got = 0;
+	let mut left = Vec::new();
+	while let Some(item) = world.fetch_item_by_name(position.0,position.1,keyword,uuid)
+	{
+		if mobile.has_room_for_item()
+		{
+			mobile.add_item(item,true);
+			got += 1;
+		}
+		else
+		{
+			left.push(item);
+		}
+	}
+	let mut result;
+	if got == 0 && left.is_empty()
+	{
+		result = "Get what?".to_string();
+	}
+	else if got == 0
+	{
+		result = "You don't have space for that!".to_string();
+	}
+	else
+	{
+		result = "Got ".to_string()+&got.to_string()+if got == 1 { " item!" } else { " items!" };
+		if !left.is_empty()
+		{
+			result += &(" You don't have space for the other ".to_string()+&left.len().to_string()+".");
+		}
+	}
+	for item in left
+	{
+		world.add_item(position.0,position.1,item);
+	}
+	world.add_mobile(mobile,position.0,position.1);
+	return result;
+}
+
+fn make_item(uuid: usize, event_q: &mut EventList, target: &String) -> String
+{
+	match target.as_ref()
+	{
+		"rawhide" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::Rawhide}));
+				return "You begin making rawhide".to_string();
+			},
+		"leatherarmor" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::LeatherArmor}));
+				return "You begin making leather armor".to_string();
+			},
+		"hidearmor" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::HideArmor }));
+				return "You begin making hide armor".to_string();
+			}
+		"pointedstick" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::PointedStick }));
+				return "You begin sharpening a stick".to_string();
+			}
+		"knife" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::StoneKnife }));
+				return "You begin knapping a stone".to_string();
+			}
+		"sword" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::Sword }));
+				return "You begin forging a sword".to_string();
+			}
+		"axe" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::Axe }));
+				return "You begin forging an axe".to_string();
+			}
+		"pick" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::Pick }));
+				return "You begin forging a pick".to_string();
+			}
+		"chainmail" =>
+			{
+				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::ChainArmor }));
+				return "You begin make a shirt of chain".to_string();
+			}
+		_ => { return "What is ".to_string()+target+&"?".to_string(); }
+	}
+}
+
+fn practice(uuid: usize, world: &mut WorldState, skill: &String) -> String
+{
+	let mut found_skill = true;
+	let mut success = false;
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	if skill == "combat"
+	{
+		success = mobile.practice_combat();
+	}
+	else if skill == "steal"
+	{
+		success = mobile.practice_steal();
+	}
+	else if skill == "stealth"
+	{
+		success = mobile.practice_stealth();
+	}
+	else if skill == "perception"
+	{
+		success = mobile.practice_perception();
+	}
+	else if skill == "knowledge"
+	{
+		success = mobile.practice_knowledge();
+	}
+	else if skill == "leatherwork"
+	{
+		success = mobile.practice_leatherwork();
+	}
+	else if skill == "woodcraft"
+	{
+		success = mobile.practice_woodcraft();
+	}
+	else if skill == "metalwork"
+	{
+		success = mobile.practice_metalwork();
+	}
+	else
+	{
+		found_skill = false; 
+	}
+	world.add_mobile(mobile,position.0,position.1);
+	if !found_skill
+	{
+		return "Practice what?".to_string();
+	}
+	else if !success
+	{
+		return "Not enough xp!".to_string();
+	}
+	return "You have improved at ".to_string()+&skill+"!";
+}
+
+// Leave the item here. In town a collector takes curios for xp, and
+// the name of the curio is returned.
+fn put_down(world: &mut WorldState, mobile: &mut Box<Mobile>, position: (i16,i16), item: Box<items::Item>) -> Option<String>
+{
+	if item.xp_in_town_only && world.get_location_type(position.0, position.1) == LocationTypeCode::Town
+	{
+		mobile.xp += item.xp_value;
+		return Some(item.name.clone());
+	}
+	world.add_item(position.0,position.1,item);
+	return None;
+}
+
+fn drop_item(uuid: usize, world: &mut WorldState, target: &String) -> String
+{
+	match all_keyword(target)
+	{
+		Some(keyword) => { return drop_all(uuid,world,&keyword); },
+		None => { () }
+	}
+	let mut result = "Dropped it!".to_string();
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let item = mobile.fetch_item_by_name(&target);
+	if item.is_some()
+	{
+		match put_down(world,&mut mobile,position,item.unwrap())
+		{
+			Some(name) => { result = "A collector eagerly accepts the ".to_string()+&name+"!"; },
+			None => { () }
+		}
+	}
+	else
+	{
+		result = "Drop what?".to_string();
+	}
+	world.add_mobile(mobile,position.0,position.1);
+	return result;
+}
+
+// Drop everything carried that matches the keyword
+fn drop_all(uuid: usize, world: &mut WorldState, keyword: &String) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let mut dropped = 0;
+	let mut collected = Vec::new();
+	while let Some(item) = mobile.fetch_item_by_name(keyword)
+	{
+		match put_down(world,&mut mobile,position,item)
+		{
+			Some(name) => { collected.push(name); },
+			None => { dropped += 1; }
+		}
+	}
+	world.add_mobile(mobile,position.0,position.1);
+	if dropped == 0 && collected.is_empty()
+	{
+		return "Drop what?".to_string();
+	}
+	let mut result = String::new();
+	if dropped > 0
+	{
+		result = "Dropped ".to_string()+&dropped.to_string()+if dropped == 1 { " item!" } else { " items!" };
+	}
+	if !collected.is_empty()
+	{
+		if !result.is_empty()
+		{
+			result += " ";
+		}
+		result += &("A collector eagerly accepts the ".to_string()+&collected.join(", ")+"!");
+	}
+	return result;
+}
+
+fn eat_item(uuid: usize, world: &mut WorldState, target: &String) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let result = mobile.eat_item_by_name(target);
+	world.add_mobile(mobile,position.0,position.1);
+	return result;
+}
+
+// Wield, wear or remove something
+fn change_equipment(uuid: usize, world: &mut WorldState, target: &String, change: fn(&mut Mobile,&String) -> String) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let result = change(&mut mobile,target);
+	world.add_mobile(mobile,position.0,position.1);
+	return result;
+}
+
+fn show_equipment(uuid: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile(uuid).unwrap();
+	let equipment = mobile.list_equipment();
+	world.add_mobile(mobile,position.0,position.1);
+	return "You are using:\n".to_string()+&equipment;
+}
+
+fn give(uuid: usize, world: &mut WorldState, key: &String, target: &String) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut giver = world.fetch_mobile(uuid).unwrap();
+	let receiver = world.fetch_mobile_by_name(position.0,position.1,target,uuid);
+	if receiver.is_none()
+	{
+		world.add_mobile(giver,position.0,position.1);
+		return "Give it to whom?".to_string();
+	}
+	let mut receiver = receiver.unwrap();
+	let item = giver.fetch_item_by_name(key);
+	let result;
+	if item.is_none()
+	{
+		result = "You aren't carrying that.".to_string();
+	}
+	else
+	{
+		let item = item.unwrap();
+		let item_name = "{y}".to_string()+&item.get_name()+"{x}";
+		let article = items::indefinite_article(&item.get_name());
+		if receiver.has_room_for_item()
+		{
+			result = "You give the ".to_string()+&item_name+" to "+&receiver.name_with_article+".";
+			world.message_list.post_for_target(giver.name_with_article.clone()+" gives you "+article+" "+&item_name+".",receiver.get_id());
+			world.message_list.post_for_bystanders(giver.name_with_article.clone()+" gives "+article+" "+&item_name+" to "+&receiver.name_with_article+".",
+				position.0,position.1,giver.get_id(),receiver.get_id());
+			receiver.add_item(item,false);
+		}
+		else
+		{
+			result = receiver.name_with_article.clone()+" doesn't have room for that!";
+			giver.add_item(item,false);
+		}
+	}
+	world.add_mobile(receiver,position.0,position.1);
+	world.add_mobile(giver,position.0,position.1);
+	return result;
+}
+
+// Ask another player here to trade, or call off a trade
+fn trade(uuid: usize, world: &mut WorldState, online: &[OnlinePlayer], target: Option<&String>) -> String
+{
+	let name = world.get_mobile_name(uuid).unwrap();
+	if target.is_none()
+	{
+		return show_trade(uuid,world);
+	}
+	let target = target.unwrap();
+	if target == "cancel"
+	{
+		if world.trades.partner(uuid).is_none()
+		{
+			return "You aren't trading with anyone.".to_string();
+		}
+		let partner = world.trades.cancel(uuid);
+		if partner.is_some()
+		{
+			world.message_list.post_for_target(name+" calls off the trade.",partner.unwrap());
+		}
+		return "You call off the trade.".to_string();
+	}
+	let position = world.find_mobile_location(uuid).unwrap();
+	let other = world.get_mobile_id_by_name(position.0,position.1,target,uuid);
+	if other.is_none() || !online.iter().any(|player| Some(player.uuid) == other)
+	{
+		return "There is no player here by that name.".to_string();
+	}
+	let other = other.unwrap();
+	if other == uuid
+	{
+		return "You can't trade with yourself.".to_string();
+	}
+	let other_name = world.get_mobile_name(other).unwrap();
+	if world.trades.is_open(uuid) && world.trades.partner(uuid) == Some(other)
+	{
+		return "You are already trading with ".to_string()+&other_name+".";
+	}
+	let partner = world.trades.cancel(uuid);
+	if partner.is_some()
+	{
+		world.message_list.post_for_target(name.clone()+" calls off the trade.",partner.unwrap());
+	}
+	if world.trades.propose(uuid,other)
+	{
+		world.message_list.post_for_target(name.clone()+" agrees to trade. Use offer <object> and accept.",other);
+		return "You are trading with ".to_string()+&other_name+". Use offer <object> and accept.";
+	}
+	world.message_list.post_for_target(name.clone()+" wants to trade with you. Type trade "+&name+" to agree.",other);
+	return "You ask ".to_string()+&other_name+" to trade.";
+}
+
+fn show_trade(uuid: usize, world: &mut WorldState) -> String
+{
+	let partner = world.trades.partner(uuid);
+	if partner.is_none()
+	{
+		return "You aren't trading with anyone.".to_string();
+	}
+	let partner = partner.unwrap();
+	let partner_name = world.get_mobile_name(partner).unwrap_or("someone".to_string());
+	if !world.trades.is_open(uuid)
+	{
+		return "You are waiting for ".to_string()+&partner_name+" to agree to trade.";
+	}
+	let describe = |offer: Vec<String>| -> String
+		{
+			if offer.is_empty() { return "nothing".to_string(); }
+			return "{y}".to_string()+&offer.join("{x}, {y}")+"{x}";
+		};
+	return "Trading with ".to_string()+&partner_name+".\nYou offer: "+&describe(world.trades.offer(uuid))+
+		"\n"+&partner_name+" offers: "+&describe(world.trades.offer(partner));
+}
+
+// The player that this one is trading with, if they are still here
+fn trade_partner(uuid: usize, world: &mut WorldState) -> Result<usize,String>
+{
+	if !world.trades.is_open(uuid)
+	{
+		return Err("You aren't trading with anyone.".to_string());
+	}
+	let partner = world.trades.partner(uuid).unwrap();
+	if world.find_mobile_location(partner) != world.find_mobile_location(uuid)
+	{
+		world.trades.cancel(uuid);
+		return Err("Your trading partner isn't here. The trade is off.".to_string());
+	}
+	return Ok(partner);
+}
+
+fn offer(uuid: usize, world: &mut WorldState, key: &String) -> String
+{
+	let partner = match trade_partner(uuid,world)
+	{
+		Ok(partner) => { partner },
+		Err(message) => { return message; }
+	};
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile(uuid).unwrap();
+	let item_name = mobile.find_item_by_name(key).map(|pos| mobile.inventory[pos].name.clone());
+	let carried = match &item_name
+	{
+		Some(item_name) => { mobile.inventory.iter().filter(|item| &item.name == item_name).count() },
+		None => { 0 }
+	};
+	let name = mobile.name.clone();
+	world.add_mobile(mobile,position.0,position.1);
+	if item_name.is_none()
+	{
+		return "You aren't carrying that.".to_string();
+	}
+	let item_name = item_name.unwrap();
+	let offered = world.trades.offer(uuid).iter().filter(|offered| **offered == item_name).count();
+	if offered >= carried
+	{
+		return "You have already offered that.".to_string();
+	}
+	world.trades.add_offer(uuid,item_name.clone());
+	world.message_list.post_for_target(name+" offers "+items::indefinite_article(&item_name)+" {y}"+&item_name+"{x}.",partner);
+	return "You offer the {y}".to_string()+&item_name+"{x}.";
+}
+
+fn accept(uuid: usize, world: &mut WorldState) -> String
+{
+	let partner = match trade_partner(uuid,world)
+	{
+		Ok(partner) => { partner },
+		Err(message) => { return message; }
+	};
+	let name = world.get_mobile_name(uuid).unwrap();
+	if !world.trades.accept(uuid)
+	{
+		world.message_list.post_for_target(name+" accepts the trade.",partner);
+		return "You accept the trade.".to_string();
+	}
+	return complete_trade(uuid,partner,world);
+}
+
+// Take the offered items out of an inventory. If any of them has gone,
+// the rest are put back.
+fn take_offered(mobile: &mut Box<Mobile>, offer: &[String]) -> Option<Vec<Box<items::Item> > >
+{
+	let mut taken = Vec::new();
+	for item_name in offer.iter()
+	{
+		match mobile.fetch_item_by_name(item_name)
+		{
+			Some(item) => { taken.push(item); },
+			None =>
+				{
+					for item in taken
+					{
+						mobile.add_item(item,false);
+					}
+					return None;
+				}
+		}
+	}
+	return Some(taken);
+}
+
+fn give_all(mobile: &mut Box<Mobile>, items: Vec<Box<items::Item> >)
+{
+	for item in items
+	{
+		mobile.add_item(item,false);
+	}
+}
+
+// Swap the offered items. Either everything changes hands or nothing does.
+fn complete_trade(uuid: usize, partner: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut a = world.fetch_mobile(uuid).unwrap();
+	let mut b = world.fetch_mobile(partner).unwrap();
+	let a_items = take_offered(&mut a,&world.trades.offer(uuid));
+	let b_items = take_offered(&mut b,&world.trades.offer(partner));
+	let result;
+	if a_items.is_none() || b_items.is_none()
+	{
+		if a_items.is_some() { give_all(&mut a,a_items.unwrap()); }
+		if b_items.is_some() { give_all(&mut b,b_items.unwrap()); }
+		world.trades.cancel(uuid);
+		result = "Something on offer has gone. The trade is off.".to_string();
+		world.message_list.post_for_target(result.clone(),partner);
+	}
+	else
+	{
+		let a_items = a_items.unwrap();
+		let b_items = b_items.unwrap();
+		if !a.has_room_for_items(b_items.len()) || !b.has_room_for_items(a_items.len())
+		{
+			let full = if a.has_room_for_items(b_items.len()) { b.name.clone() } else { a.name.clone() };
+			give_all(&mut a,a_items);
+			give_all(&mut b,b_items);
+			world.trades.withdraw(uuid);
+			world.trades.withdraw(partner);
+			result = full+" doesn't have room for everything. Accept again when there is room.";
+			world.message_list.post_for_target(result.clone(),partner);
+		}
+		else
+		{
+			give_all(&mut a,b_items);
+			give_all(&mut b,a_items);
+			world.trades.cancel(uuid);
+			result = "The trade is done.".to_string();
+			world.message_list.post_for_target(a.name.clone()+" accepts the trade. The trade is done.",partner);
+		}
+	}
+	world.add_mobile(a,position.0,position.1);
+	world.add_mobile(b,position.0,position.1);
+	return result;
+}
+
+fn hide(uuid: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile(uuid).unwrap();
+	let fighting = mobile.fighting.iter().any(|opponent| world.find_mobile_location(*opponent) == Some(position));
+	let hidden = mobile.hidden;
+	world.add_mobile(mobile,position.0,position.1);
+	if fighting
+	{
+		return "You can't hide in the middle of a fight!".to_string();
+	}
+	else if hidden
+	{
+		return "You are already hidden.".to_string();
+	}
+	world.hide_mobile(position.0,position.1,uuid);
+	return "You find a place to hide.".to_string();
+}
+
+// Returns true if the mobile was hidden
+fn stop_hiding(uuid: usize, world: &mut WorldState) -> bool
+{
+	let position = world.find_mobile_location(uuid);
+	if position.is_none()
+	{
+		return false;
+	}
+	let position = position.unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let was_hidden = mobile.stop_hiding();
+	world.add_mobile(mobile,position.0,position.1);
+	return was_hidden;
+}
+
+fn search(uuid: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile(uuid).unwrap();
+	let found = world.search(position.0,position.1,&mobile);
+	world.add_mobile(mobile,position.0,position.1);
+	if found.is_empty()
+	{
+		return "You search carefully but find nothing.".to_string();
+	}
+	let lines: Vec<String> = found.iter().map(|thing| "You find ".to_string()+thing+"!").collect();
+	return lines.join("\n");
+}
+
+fn sneak(uuid: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	mobile.sneaking = !mobile.sneaking;
+	let result = if mobile.sneaking { "You start moving quietly." } else { "You stop sneaking." };
+	world.add_mobile(mobile,position.0,position.1);
+	return result.to_string();
+}
+
+fn kill(uuid: usize, world: &mut WorldState, event_q: &mut EventList, target: &String)
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let defender = world.get_mobile_id_by_name(position.0,position.1,&target,uuid);
+	match defender
+	{
+		Some(defender) => { event_q.insert(Box::new(CombatEvent { attacker: uuid, defender: defender, first_round: true })); },
+		_ => { return; }
+	}
+}
+
+fn steal(uuid: usize, world: &mut WorldState, event_q: &mut EventList, target: &String)
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mark = world.get_mobile_id_by_name(position.0,position.1,&target,uuid);
+	match mark
+	{
+		Some(mark) => { event_q.insert(Box::new(StealEvent { thief: uuid, mark: mark })); },
+		_ => { return; }
+	}
+}
+
+// Sizes of the map window, counted in locations across
+const DEFAULT_MAP_SIZE: i16 = 9;
+const MAX_MAP_SIZE: i16 = 21;
+
+// A map of the places around the player that they have been to
+fn show_map(uuid: usize, world: &mut WorldState, size: Option<&String>) -> String
+{
+	let size = match size
+	{
+		Some(size) =>
+			{
+				match size.parse::<i16>()
+				{
+					// You are in the middle, so the size is odd
+					Ok(size) if (1..=MAX_MAP_SIZE).contains(&size) && size % 2 == 1 => { size },
+					_ => { return "The map can be an odd number from 1 to ".to_string()+&MAX_MAP_SIZE.to_string()+" across."; }
+				}
+			},
+		None => { DEFAULT_MAP_SIZE }
+	};
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile(uuid).unwrap();
+	let map = world.draw_map_window(position.0,position.1,size/2,&mobile.visited);
+	world.add_mobile(mobile,position.0,position.1);
+	return map+"@ you  T town  - forest  * deep woods  ^ hills";
+}
+
+// Roughly where a player is, without giving away the exact place
+fn region(x: i16, y: i16, location_type: LocationTypeCode) -> String
+{
+	if x == 0 && y == 0
+	{
+		return "in Midgaard".to_string();
+	}
+	if x.abs() <= 3 && y.abs() <= 3
+	{
+		return "near Midgaard".to_string();
+	}
+	return "in the ".to_string()+&location_type.name().to_lowercase();
+}
+
+fn idle_time(idle: Duration) -> String
+{
+	let seconds = idle.as_secs();
+	if seconds < 60
+	{
+		return seconds.to_string()+"s";
+	}
+	if seconds < 3600
+	{
+		return (seconds/60).to_string()+"m";
+	}
+	return (seconds/3600).to_string()+"h";
+}
+
+fn who(world: &mut WorldState, online: &[OnlinePlayer]) -> String
+{
+	let mut result = "Players online:\n".to_string();
+	let mut count = 0;
+	for player in online.iter()
+	{
+		let position = match world.find_mobile_location(player.uuid)
+		{
+			Some(position) => { position },
+			None => { continue; }
+		};
+		let name = world.get_mobile_name(player.uuid).unwrap();
+		let location_type = world.get_location_type(position.0,position.1);
+		result += &format!("{:20} idle {:4} {}\n",name,idle_time(player.idle),region(position.0,position.1,location_type));
+		count += 1;
+	}
+	result += &(count.to_string()+if count == 1 { " player." } else { " players." });
+	return result;
+}
+
+// Talking. What players say is escaped so that it cannot change colours.
+
+fn say(uuid: usize, world: &mut WorldState, text: &str) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let name = world.get_mobile_name(uuid).unwrap();
+	let text = color::escape(text);
+	world.message_list.post_no_echo("{c}".to_string()+&name+" says, '"+&text+"'{x}",position.0,position.1,uuid);
+	return "{c}You say, '".to_string()+&text+"'{x}";
+}
+
+fn tell(uuid: usize, world: &mut WorldState, online: &[OnlinePlayer], target: &str, text: &str) -> String
+{
+	let name = world.get_mobile_name(uuid).unwrap();
+	let mut listener = None;
+	for other in online.iter()
+	{
+		match world.get_mobile_name(other.uuid)
+		{
+			Some(other_name) if other_name.eq_ignore_ascii_case(target) => { listener = Some((other.uuid,other_name)); break; },
+			_ => { () }
+		}
+	}
+	match listener
+	{
+		Some((other,_)) if other == uuid => { return "You mutter to yourself.".to_string(); },
+		Some((other,other_name)) =>
+			{
+				let text = color::escape(text);
+				world.message_list.post_for_target("{m}".to_string()+&name+" tells you, '"+&text+"'{x}",other);
+				return "{m}You tell ".to_string()+&other_name+", '"+&text+"'{x}";
+			},
+		None => { return "Nobody called ".to_string()+&color::escape(target)+" is playing."; }
+	}
+}
+
+fn shout(uuid: usize, world: &mut WorldState, radius: i16, text: &str) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let name = world.get_mobile_name(uuid).unwrap();
+	let text = color::escape(text);
+	world.message_list.post_within("{Y}".to_string()+&name+" shouts, '"+&text+"'{x}",position.0,position.1,radius,uuid);
+	return "{Y}You shout, '".to_string()+&text+"'{x}";
+}
+
+fn emote(uuid: usize, world: &mut WorldState, text: &str) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let name = world.get_mobile_name(uuid).unwrap();
+	let action = name+" "+&color::escape(text);
+	world.message_list.post_no_echo(action.clone(),position.0,position.1,uuid);
+	return action;
+}
+
+// A canned emote. In the messages $n is the one doing it and $t is
+// the one it is done to.
+pub struct Social
+{
+	pub name: &'static str,
+	pub help: &'static str,
+	// Done to nobody, as seen by the one doing it and by the others here
+	pub alone_self: &'static str,
+	pub alone_room: &'static str,
+	// Done to someone, as seen by the one doing it, the others here, and the target
+	pub target_self: &'static str,
+	pub target_room: &'static str,
+	pub target_victim: &'static str
+}
+
+pub const SOCIALS: [Social; 8] = [
+	Social { name: "smile", help: "Smile, or smile at someone.", alone_self: "You smile.", alone_room: "$n smiles.",
+		target_self: "You smile at $t.", target_room: "$n smiles at $t.", target_victim: "$n smiles at you." },
+	Social { name: "grin", help: "Grin, or grin at someone.", alone_self: "You grin.", alone_room: "$n grins.",
+		target_self: "You grin at $t.", target_room: "$n grins at $t.", target_victim: "$n grins at you." },
+	Social { name: "nod", help: "Nod, or nod to someone.", alone_self: "You nod.", alone_room: "$n nods.",
+		target_self: "You nod to $t.", target_room: "$n nods to $t.", target_victim: "$n nods to you." },
+	Social { name: "wave", help: "Wave, or wave to someone.", alone_self: "You wave.", alone_room: "$n waves.",
+		target_self: "You wave to $t.", target_room: "$n waves to $t.", target_victim: "$n waves to you." },
+	Social { name: "laugh", help: "Laugh, or laugh at someone.", alone_self: "You laugh.", alone_room: "$n laughs.",
+		target_self: "You laugh at $t.", target_room: "$n laughs at $t.", target_victim: "$n laughs at you." },
+	Social { name: "bow", help: "Bow, or bow to someone.", alone_self: "You bow.", alone_room: "$n bows.",
+		target_self: "You bow to $t.", target_room: "$n bows to $t.", target_victim: "$n bows to you." },
+	Social { name: "shrug", help: "Shrug, or shrug at someone.", alone_self: "You shrug.", alone_room: "$n shrugs.",
+		target_self: "You shrug at $t.", target_room: "$n shrugs at $t.", target_victim: "$n shrugs at you." },
+	Social { name: "cheer", help: "Cheer, or cheer someone on.", alone_self: "You cheer.", alone_room: "$n cheers.",
+		target_self: "You cheer $t on.", target_room: "$n cheers $t on.", target_victim: "$n cheers you on." }
+];
+
+pub struct SocialCommand
+{
+	social: &'static Social,
+	arguments: Arguments
+}
+
+impl SocialCommand
+{
+	pub fn new(social: &'static Social) -> SocialCommand
+	{
+		return SocialCommand { social: social, arguments: Arguments::Optional };
+	}
+}
+
+impl Command for SocialCommand
+{
+	fn name(&self) -> &str { return self.social.name; }
+	fn aliases(&self) -> &[&str] { return &[]; }
+	fn abbreviation(&self) -> usize { return self.social.name.len(); }
+	fn arguments(&self) -> &Arguments { return &self.arguments; }
+	fn usage(&self) -> &str { return "[someone]"; }
+	fn help(&self) -> &str { return self.social.help; }
+	fn admin_only(&self) -> bool { return false; }
+	fn quiet(&self) -> bool { return false; }
+
+	fn run(&self, args: &[String], context: &mut CommandContext) -> String
+	{
+		let uuid = context.uuid;
+		let world = &mut *context.world;
+		let position = world.find_mobile_location(uuid).unwrap();
+		let name = world.get_mobile_name(uuid).unwrap();
+		let social = self.social;
+		match args.first()
+		{
+			None =>
+				{
+					world.message_list.post_no_echo(social.alone_room.replace("$n",&name),position.0,position.1,uuid);
+					return social.alone_self.to_string();
+				},
+			Some(target) =>
+				{
+					let other = match world.fetch_mobile_by_name(position.0,position.1,target,uuid)
+					{
+						Some(other) => { other },
+						None => { return "They aren't here.".to_string(); }
+					};
+					let other_id = other.get_id();
+					let other_name = other.name_with_article.clone();
+					world.add_mobile(other,position.0,position.1);
+					world.message_list.post_for_bystanders(social.target_room.replace("$n",&name).replace("$t",&other_name),position.0,position.1,uuid,other_id);
+					world.message_list.post_for_target(social.target_victim.replace("$n",&name),other_id);
+					return social.target_self.replace("$t",&other_name);
+				}
+		}
+	}
+}
+
+fn goto(uuid: usize, dx: i16, dy: i16, event_q: &mut EventList)
+{
+	let move_event = Box::new(MoveMobileEvent
+		{
+			uuid: uuid,
+			dx: dx,
+			dy: dy
+		});
+	event_q.insert(move_event);
+}
+
+fn look_at(uuid: usize, world: &mut WorldState, target: &String) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile_by_name(position.0,position.1,&target,uuid);
+	if mobile.is_some()
+	{
+		let mobile = mobile.unwrap();
+		let description = mobile.description()+"\n"+&mobile.list_equipment()+"Carrying:\n"+&mobile.list_inventory();
+		world.add_mobile(mobile,position.0,position.1);
+		return description;
+	}
+	let item = world.fetch_item_by_name(position.0,position.1,&target,uuid);
+	if item.is_some()
+	{
+		let item = item.unwrap();
+		let description = item.description();
+		world.add_item(position.0,position.1,item);
+		return description;
+	}
+	return "Look at what?".to_string();	
+}
+
+fn look(uuid: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	return world.get_location_description(position.0,position.1,uuid);
+}
+
+fn show_inventory(uuid: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile(uuid).unwrap();
+	let inventory = mobile.list_inventory();
+	world.add_mobile(mobile,position.0,position.1);
+	return ("You have:\n".to_owned()+&inventory).to_string();
+}
+
+fn show_stats_of(uuid: usize, world: &mut WorldState, target: &String) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let scholar = world.fetch_mobile(uuid).unwrap();
+	// Is this a mobile at our location?
+	let mobile = world.fetch_mobile_by_name(position.0,position.1,&target,uuid);
+	if mobile.is_some()
+	{
+		let mobile = mobile.unwrap();
+		let success = scholar.roll_knowledge() > mobile.frequency;
+		let description =
+			if !success { "Perhaps you should study harder?".to_string() } 
+			else { mobile.complete_description() };
+		world.add_mobile(mobile,position.0,position.1);
+		world.add_mobile(scholar,position.0,position.1);
+		return description;
+	}
+	// Is this an item at our location?
+	let item = world.fetch_item_by_name(position.0,position.1,&target,uuid);
+	if item.is_some()
+	{
+		let item = item.unwrap();
+		let success = scholar.roll_knowledge() > item.frequency;
+		let description =
+			if !success { "Perhaps you should study harder?".to_string() } 
+			else { item.complete_description() };
+		world.add_item(position.0,position.1,item);
+		world.add_mobile(scholar,position.0,position.1);
+		return description;
+	}
+	// Nope. Nothing to stat.
+	world.add_mobile(scholar,position.0,position.1);
+	return "Stat what?".to_string();	
+}
+
+fn show_stats(uuid: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile(uuid).unwrap();
+	let result = mobile.complete_description();
+	world.add_mobile(mobile,position.0,position.1);
+	return result;
+}
+
+fn set_color(uuid: usize, world: &mut WorldState, setting: Option<&String>) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let mut result = String::new();
+	match setting.map(|s| s.as_str())
+	{
+		Some("on") => { mobile.color = true; },
+		Some("off") => { mobile.color = false; },
+		None => { mobile.color = !mobile.color; },
+		_ => { result = "Usage: color [on|off]".to_string(); }
+	}
+	if result.is_empty()
+	{
+		result = if mobile.color { "Color is {G}on{x}.".to_string() } else { "Color is off.".to_string() };
+	}
+	world.add_mobile(mobile,position.0,position.1);
+	return result;
+}
+
+// Admins can stop the server after a countdown, or call the countdown off
+fn shutdown(world: &mut WorldState, setting: Option<&String>, config: &Config) -> String
+{
+	let seconds = match setting.map(|s| s.as_str())
+	{
+		Some("cancel") =>
+			{
+				if world.shutdown_at.is_none()
+				{
+					return "There is no shutdown to cancel.".to_string();
+				}
+				world.shutdown_at = None;
+				world.message_list.post_for_everyone("{G}The shutdown has been cancelled.{x}".to_string());
+				return "Shutdown cancelled.".to_string();
+			},
+		Some(seconds) =>
+			{
+				match seconds.parse::<u16>()
+				{
+					Ok(seconds) => { seconds },
+					Err(_e) => { return "Usage: shutdown [seconds|cancel]".to_string(); }
+				}
+			},
+		None => { config.shutdown_seconds }
+	};
+	world.shutdown_at = Some(Instant::now()+Duration::from_secs(seconds.into()));
+	return "Shutting down in ".to_string()+&seconds.to_string()+" seconds.";
+}
+
+// Show or change the prompt. The template is saved with the character.
+fn set_prompt(uuid: usize, world: &mut WorldState, template: &str) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mut mobile = world.fetch_mobile(uuid).unwrap();
+	let result;
+	if template.is_empty()
+	{
+		result = "Your prompt is: ".to_string()+&color::escape(&mobile.prompt)+"\nUse %h for damage taken out of the most you can take, %x for experience, %c for where you are, %t for the terrain and %% for a percent sign.";
+	}
+	else if template.chars().count() > prompt::MAX_PROMPT_LENGTH
+	{
+		result = "That prompt is too long.".to_string();
+	}
+	else
+	{
+		mobile.prompt = if template == "default" { prompt::DEFAULT_PROMPT.to_string() } else { template.to_string() };
+		result = "Prompt set.".to_string();
+	}
+	world.add_mobile(mobile,position.0,position.1);
+	return result;
+}
+
+// The session asks for the passwords so that they are not echoed
+fn change_password(uuid: usize, world: &mut WorldState) -> String
+{
+	let position = world.find_mobile_location(uuid).unwrap();
+	let mobile = world.fetch_mobile(uuid).unwrap();
+	let name = mobile.name.clone();
+	world.add_mobile(mobile,position.0,position.1);
+	world.password_requests.push(PasswordRequest { uuid: uuid, name: name, check_old: true });
+	return String::new();
+}
+
+// Give a character a new password. This is how characters saved
+// before there were accounts are unlocked.
+fn set_password(uuid: usize, world: &mut WorldState, name: &String) -> String
+{
+	let file_name = Path::new(&world.save_directory).join(name.to_ascii_lowercase()+".csv");
+	if !Account::valid_name(name) || !file_name.exists()
+	{
+		return "There is no character called ".to_string()+name+".";
+	}
+	world.password_requests.push(PasswordRequest { uuid: uuid, name: name.clone(), check_old: false });
+	return String::new();
+}
+
+// The commands in the order that the help lists them
+impl CommandRegistry
+{
+	pub fn standard() -> CommandRegistry
+	{
+		let mut commands = CommandRegistry::new();
+		let list = [
+			BasicCommand { name: "north", aliases: &["n"], abbreviation: 1, arguments: Arguments::Nothing, usage: "", help: "Go north.", admin_only: false, quiet: false,
+				handler: |_args,context| { goto(context.uuid,0,1,context.event_q); return String::new(); } },
+			BasicCommand { name: "south", aliases: &["s"], abbreviation: 1, arguments: Arguments::Nothing, usage: "", help: "Go south.", admin_only: false, quiet: false,
+				handler: |_args,context| { goto(context.uuid,0,-1,context.event_q); return String::new(); } },
+			BasicCommand { name: "east", aliases: &["e"], abbreviation: 1, arguments: Arguments::Nothing, usage: "", help: "Go east.", admin_only: false, quiet: false,
+				handler: |_args,context| { goto(context.uuid,1,0,context.event_q); return String::new(); } },
+			BasicCommand { name: "west", aliases: &["w"], abbreviation: 1, arguments: Arguments::Nothing, usage: "", help: "Go west.", admin_only: false, quiet: false,
+				handler: |_args,context| { goto(context.uuid,-1,0,context.event_q); return String::new(); } },
+			BasicCommand { name: "northeast", aliases: &["ne"], abbreviation: 6, arguments: Arguments::Nothing, usage: "", help: "Go northeast.", admin_only: false, quiet: false,
+				handler: |_args,context| { goto(context.uuid,1,1,context.event_q); return String::new(); } },
+			BasicCommand { name: "northwest", aliases: &["nw"], abbreviation: 6, arguments: Arguments::Nothing, usage: "", help: "Go northwest.", admin_only: false, quiet: false,
+				handler: |_args,context| { goto(context.uuid,-1,1,context.event_q); return String::new(); } },
+			BasicCommand { name: "southeast", aliases: &["se"], abbreviation: 6, arguments: Arguments::Nothing, usage: "", help: "Go southeast.", admin_only: false, quiet: false,
+				handler: |_args,context| { goto(context.uuid,1,-1,context.event_q); return String::new(); } },
+			BasicCommand { name: "southwest", aliases: &["sw"], abbreviation: 6, arguments: Arguments::Nothing, usage: "", help: "Go southwest.", admin_only: false, quiet: false,
+				handler: |_args,context| { goto(context.uuid,-1,-1,context.event_q); return String::new(); } },
+			BasicCommand { name: "up", aliases: &["u"], abbreviation: 1, arguments: Arguments::Nothing, usage: "", help: "Climb up, where there is a way.", admin_only: false, quiet: false,
+				handler: |_args,context| { context.event_q.insert(Box::new(UseExitEvent { uuid: context.uuid, direction: "up" })); return String::new(); } },
+			BasicCommand { name: "down", aliases: &["d"], abbreviation: 2, arguments: Arguments::Nothing, usage: "", help: "Climb down, where there is a way.", admin_only: false, quiet: false,
+				handler: |_args,context| { context.event_q.insert(Box::new(UseExitEvent { uuid: context.uuid, direction: "down" })); return String::new(); } },
+			BasicCommand { name: "help", aliases: &["?"], abbreviation: 1, arguments: Arguments::Optional, usage: "[command]", help: "This message, or more about one command.", admin_only: false, quiet: true,
+				handler: |args,context| { let admin = context.is_admin(); return context.commands.help(args.first(),admin); } },
+			BasicCommand { name: "look", aliases: &[], abbreviation: 1, arguments: Arguments::Optional, usage: "[object]", help: "Look at your surroundings, or at an object.", admin_only: false, quiet: true,
+				handler: |args,context|
+					{
+						match args.first()
+						{
+							Some(target) => { return look_at(context.uuid,context.world,target); },
+							None => { return look(context.uuid,context.world); }
+						}
+					} },
+			BasicCommand { name: "eat", aliases: &[], abbreviation: 3, arguments: Arguments::Required("Eat what?"), usage: "<object>", help: "Try to eat or drink an object.", admin_only: false, quiet: false,
+				handler: |args,context| { return eat_item(context.uuid,context.world,&args[0]); } },
+			BasicCommand { name: "kill", aliases: &[], abbreviation: 1, arguments: Arguments::Required("Kill what?"), usage: "<object>", help: "Attack an object.", admin_only: false, quiet: false,
+				handler: |args,context| { kill(context.uuid,context.world,context.event_q,&args[0]); return String::new(); } },
+			BasicCommand { name: "flee", aliases: &[], abbreviation: 1, arguments: Arguments::Nothing, usage: "", help: "Try to run away from a fight.", admin_only: false, quiet: false,
+				handler: |_args,context| { context.event_q.insert(Box::new(FleeEvent { uuid: context.uuid })); return String::new(); } },
+			BasicCommand { name: "hide", aliases: &[], abbreviation: 2, arguments: Arguments::Nothing, usage: "", help: "Hide until you do something. Only those\nwho notice you will see you here.", admin_only: false, quiet: true,
+				handler: |_args,context| { return hide(context.uuid,context.world); } },
+			BasicCommand { name: "sneak", aliases: &[], abbreviation: 2, arguments: Arguments::Nothing, usage: "", help: "Turn sneaking on or off. Those who don't\nnotice you won't see you come and go.", admin_only: false, quiet: true,
+				handler: |_args,context| { return sneak(context.uuid,context.world); } },
+			BasicCommand { name: "search", aliases: &[], abbreviation: 3, arguments: Arguments::Nothing, usage: "", help: "Look for hidden things here.", admin_only: false, quiet: false,
+				handler: |_args,context| { return search(context.uuid,context.world); } },
+			BasicCommand { name: "steal", aliases: &[], abbreviation: 3, arguments: Arguments::Required("Steal from whom?"), usage: "<object>", help: "Try to steal something from the object.", admin_only: false, quiet: false,
+				handler: |args,context| { steal(context.uuid,context.world,context.event_q,&args[0]); return String::new(); } },
+			BasicCommand { name: "get", aliases: &["take"], abbreviation: 1, arguments: Arguments::Required("Get what?"), usage: "<object>|all[.<object>]", help: "Pick something up, or everything that matches.", admin_only: false, quiet: false,
+				handler: |args,context| { return get_item(context.uuid,context.world,&args[0]); } },
+			BasicCommand { name: "drop", aliases: &[], abbreviation: 2, arguments: Arguments::Required("Drop what?"), usage: "<object>|all[.<object>]", help: "Drop something, or everything that matches.", admin_only: false, quiet: false,
+				handler: |args,context| { return drop_item(context.uuid,context.world,&args[0]); } },
+			BasicCommand { name: "give", aliases: &[], abbreviation: 2, arguments: Arguments::Pair, usage: "<object> <target>", help: "Hand something to someone here.", admin_only: false, quiet: false,
+				handler: |args,context| { return give(context.uuid,context.world,&args[0],&args[1]); } },
+			BasicCommand { name: "trade", aliases: &[], abbreviation: 2, arguments: Arguments::Optional, usage: "[player|cancel]",
+				help: "Trade items with another player here.\nYou both need to ask to trade with each\nother, then offer items and accept.\nOn its own it shows what is on offer.", admin_only: false, quiet: false,
+				handler: |args,context| { return trade(context.uuid,context.world,context.online,args.first()); } },
+			BasicCommand { name: "offer", aliases: &[], abbreviation: 2, arguments: Arguments::Required("Offer what?"), usage: "<object>", help: "Put something up for trade.", admin_only: false, quiet: false,
+				handler: |args,context| { return offer(context.uuid,context.world,&args[0]); } },
+			BasicCommand { name: "accept", aliases: &[], abbreviation: 3, arguments: Arguments::Nothing, usage: "", help: "Agree to the trade as it stands.", admin_only: false, quiet: false,
+				handler: |_args,context| { return accept(context.uuid,context.world); } },
+			BasicCommand { name: "practice", aliases: &["prac"], abbreviation: 3, arguments: Arguments::Required("Practice what?"), usage: "<skill>",
+				help: "Practice a skill. The skills are combat,\nsteal, stealth, perception, knowledge,\nleatherwork, woodcraft, and metalwork.", admin_only: false, quiet: false,
+				handler: |args,context| { return practice(context.uuid,context.world,&args[0]); } },
+			BasicCommand { name: "stat", aliases: &["score"], abbreviation: 3, arguments: Arguments::Optional, usage: "[object]", help: "Get your statistics, or those of an object.", admin_only: false, quiet: true,
+				handler: |args,context|
+					{
+						match args.first()
+						{
+							Some(target) => { return show_stats_of(context.uuid,context.world,target); },
+							None => { return show_stats(context.uuid,context.world); }
+						}
+					} },
+			BasicCommand { name: "inventory", aliases: &["i"], abbreviation: 3, arguments: Arguments::Nothing, usage: "", help: "List your inventory.", admin_only: false, quiet: true,
+				handler: |_args,context| { return show_inventory(context.uuid,context.world); } },
+			BasicCommand { name: "equipment", aliases: &["eq"], abbreviation: 2, arguments: Arguments::Nothing, usage: "", help: "List what you are wielding and wearing.", admin_only: false, quiet: true,
+				handler: |_args,context| { return show_equipment(context.uuid,context.world); } },
+			BasicCommand { name: "wield", aliases: &[], abbreviation: 3, arguments: Arguments::Required("Wield what?"), usage: "<object>", help: "Fight with a weapon you are carrying.", admin_only: false, quiet: false,
+				handler: |args,context| { return change_equipment(context.uuid,context.world,&args[0],Mobile::wield_item_by_name); } },
+			BasicCommand { name: "wear", aliases: &[], abbreviation: 3, arguments: Arguments::Required("Wear what?"), usage: "<object>", help: "Put on armor you are carrying.", admin_only: false, quiet: false,
+				handler: |args,context| { return change_equipment(context.uuid,context.world,&args[0],Mobile::wear_item_by_name); } },
+			BasicCommand { name: "remove", aliases: &[], abbreviation: 3, arguments: Arguments::Required("Remove what?"), usage: "<object>", help: "Put a weapon or armor back in your pack.", admin_only: false, quiet: false,
+				handler: |args,context| { return change_equipment(context.uuid,context.world,&args[0],Mobile::remove_item_by_name); } },
+			BasicCommand { name: "make", aliases: &[], abbreviation: 2, arguments: Arguments::Required("Make what?"), usage: "<object>",
+				help: "Try to make something. You can try\nto make anything you like. The object\nname should be same as the item but\nwithout spaces. For example, to make\n\"leather armor\" use make leatherarmor.\nYou can make hidearmor and rawhide from\ncorpses.", admin_only: false, quiet: false,
+				handler: |args,context| { return make_item(context.uuid,context.event_q,&args[0]); } },
+			BasicCommand { name: "color", aliases: &["colour"], abbreviation: 3, arguments: Arguments::Optional, usage: "[on|off]", help: "Turn colour on or off.", admin_only: false, quiet: true,
+				handler: |args,context| { return set_color(context.uuid,context.world,args.first()); } },
+			BasicCommand { name: "prompt", aliases: &[], abbreviation: 3, arguments: Arguments::Text, usage: "[text]",
+				help: "Change your prompt. %h is your damage and\nthe most you can take, %x your experience,\n%c where you are and %t the terrain. prompt\ndefault puts it back.", admin_only: false, quiet: true,
+				handler: |args,context|
+					{
+						let template = args.first().map(|s| s.as_str()).unwrap_or("");
+						return set_prompt(context.uuid,context.world,template);
+					} },
+			BasicCommand { name: "map", aliases: &[], abbreviation: 3, arguments: Arguments::Optional, usage: "[size]", help: "Show a map of the places around you\nthat you have been to. The size is an\nodd number such as 5 or 9.", admin_only: false, quiet: true,
+				handler: |args,context| { return show_map(context.uuid,context.world,args.first()); } },
+			BasicCommand { name: "who", aliases: &[], abbreviation: 2, arguments: Arguments::Nothing, usage: "", help: "See who is playing and roughly where.", admin_only: false, quiet: true,
+				handler: |_args,context| { return who(context.world,context.online); } },
+			BasicCommand { name: "say", aliases: &[], abbreviation: 2, arguments: Arguments::RequiredText("Say what?"), usage: "<text>", help: "Say something to everyone here.", admin_only: false, quiet: false,
+				handler: |args,context| { return say(context.uuid,context.world,&args[0]); } },
+			BasicCommand { name: "tell", aliases: &[], abbreviation: 2, arguments: Arguments::WordAndText("Tell whom what?"), usage: "<player> <text>", help: "Say something to one player, wherever\nthey are.", admin_only: false, quiet: false,
+				handler: |args,context| { return tell(context.uuid,context.world,context.online,&args[0],&args[1]); } },
+			BasicCommand { name: "shout", aliases: &[], abbreviation: 2, arguments: Arguments::RequiredText("Shout what?"), usage: "<text>", help: "Shout something for everyone nearby.", admin_only: false, quiet: false,
+				handler: |args,context| { return shout(context.uuid,context.world,context.config.shout_radius,&args[0]); } },
+			BasicCommand { name: "emote", aliases: &[], abbreviation: 2, arguments: Arguments::RequiredText("Emote what?"), usage: "<action>", help: "Show everyone here what you are doing.", admin_only: false, quiet: false,
+				handler: |args,context| { return emote(context.uuid,context.world,&args[0]); } },
+			BasicCommand { name: "password", aliases: &[], abbreviation: 4, arguments: Arguments::Nothing, usage: "", help: "Change your password.", admin_only: false, quiet: true,
+				handler: |_args,context| { return change_password(context.uuid,context.world); } },
+			BasicCommand { name: "quit", aliases: &[], abbreviation: 4, arguments: Arguments::Nothing, usage: "", help: "Stash your character until next time.", admin_only: false, quiet: false,
+				handler: |_args,context| { context.world.stash_mobile(context.uuid); return "Goodbye!".to_string(); } },
+			BasicCommand { name: "shutdown", aliases: &[], abbreviation: 8, arguments: Arguments::Optional, usage: "[seconds|cancel]", help: "Stop the server after a countdown.", admin_only: true, quiet: false,
+				handler: |args,context| { return shutdown(context.world,args.first(),context.config); } },
+			BasicCommand { name: "setpassword", aliases: &[], abbreviation: 11, arguments: Arguments::Required("Set whose password?"), usage: "<player>", help: "Give a player a new password.", admin_only: true, quiet: false,
+				handler: |args,context| { return set_password(context.uuid,context.world,&args[0]); } }
+		];
+		for command in list
+		{
+			commands.register(Box::new(command));
+		}
+		for social in SOCIALS.iter()
+		{
+			commands.register(Box::new(SocialCommand::new(social)));
+		}
+		return commands;
+	}
+}
+
+#[cfg(test)]
+mod commands_unit_test
+{
+	use super::*;
+	use crate::mobile::Mobile;
+	use crate::items::Item;
+
+	#[test]
+	fn find_test()
+	{
+		let commands = CommandRegistry::standard();
+		assert_eq!(commands.find("loo",false).ok().unwrap().name(),"look");
+		assert_eq!(commands.find("inv",false).ok().unwrap().name(),"inventory");
+		assert_eq!(commands.find("i",false).ok().unwrap().name(),"inventory");
+		// Aliases typed in full beat longer names
+		assert_eq!(commands.find("s",false).ok().unwrap().name(),"south");
+		assert_eq!(commands.find("nor",false).ok().unwrap().name(),"north");
+		assert_eq!(commands.find("northe",false).ok().unwrap().name(),"northeast");
+		assert_eq!(commands.find("sw",false).ok().unwrap().name(),"southwest");
+		assert_eq!(commands.find("do",false).ok().unwrap().name(),"down");
+		assert_eq!(commands.find("we",false).ok().unwrap().name(),"west");
+		assert_eq!(commands.find("wea",false).ok().unwrap().name(),"wear");
+		assert_eq!(commands.find("eq",false).ok().unwrap().name(),"equipment");
+		// Not long enough to tell them apart
+		assert_eq!(commands.find("st",false).err().unwrap(),"Which do you mean: steal, stat?");
+		assert_eq!(commands.find("qui",false).err().unwrap(),"What?");
+		assert_eq!(commands.find("xyzzy",false).err().unwrap(),"What?");
+		// Admin commands are hidden from everyone else
+		assert!(commands.find("shutdown",false).is_err());
+		assert!(commands.find("shutdown",true).is_ok());
+		// Looking around does not give away a hiding place
+		assert!(commands.find("look",false).ok().unwrap().quiet());
+		assert!(!commands.find("get",false).ok().unwrap().quiet());
+	}
+
+	#[test]
+	fn who_test()
+	{
+		let mut world = WorldState::new();
+		let jim = Mobile::new_character(&"Jim".to_string());
+		let sally = Mobile::new_character(&"Sally".to_string());
+		let online = [
+			OnlinePlayer { uuid: jim.get_id(), idle: Duration::from_secs(5) },
+			OnlinePlayer { uuid: sally.get_id(), idle: Duration::from_secs(150) }
+		];
+		world.add_mobile(jim,0,0);
+		world.add_mobile(sally,2,-1);
+		world.add_mobile(Mobile::rabbit(),0,0);
+		let list = who(&mut world,&online);
+		assert!(list.contains("Jim                  idle 5s   in Midgaard\n"));
+		assert!(list.contains("Sally                idle 2m   near Midgaard\n"));
+		assert!(!list.contains("rabbit"));
+		assert!(list.ends_with("2 players."));
+		assert_eq!(region(10,0,LocationTypeCode::DeepWoods),"in the deep woods");
+		assert_eq!(idle_time(Duration::from_secs(7300)),"2h");
+	}
+
+	#[test]
+	fn get_drop_all_test()
+	{
+		let mut world = WorldState::new();
+		let mut mobile = Mobile::new_character(&"Jim".to_string());
+		let id = mobile.get_id();
+		mobile.add_item(Item::bone_jewelry(),false);
+		mobile.add_item(Item::gold_bauble(),false);
+		mobile.add_item(Item::sword(),false);
+		let xp = mobile.xp;
+		world.add_mobile(mobile,0,0);
+		// Midgaard's collector takes both curios
+		assert_eq!(drop_item(id,&mut world,&"all".to_string()),"Dropped 1 item! A collector eagerly accepts the bone jewlery, golden bauble!");
+		assert_eq!(world.fetch_mobile(id).map(|mobile| mobile.xp),Some(xp+10));
+		let mut mobile = Mobile::new_character(&"Jim".to_string());
+		let id = mobile.get_id();
+		mobile.misc_items_slots = 2;
+		world.add_mobile(mobile,0,0);
+		world.add_item(0,0,Item::axe());
+		world.add_item(0,0,Item::rawhide());
+		assert_eq!(get_item(id,&mut world,&"all.nothing".to_string()),"Get what?");
+		assert_eq!(get_item(id,&mut world,&"all.sword".to_string()),"Got 1 item!");
+		assert_eq!(get_item(id,&mut world,&"all".to_string()),"Got 1 item! You don't have space for the other 1.");
+		assert_eq!(get_item(id,&mut world,&"all".to_string()),"You don't have space for that!");
+		assert_eq!(drop_item(id,&mut world,&"all.sword".to_string()),"Dropped 1 item!");
+		assert_eq!(drop_item(id,&mut world,&"all.sword".to_string()),"Drop what?");
+	}
+
+	#[test]
+	fn show_map_test()
+	{
+		let mut world = WorldState::new();
+		let mobile = Mobile::new_character(&"Jim".to_string());
+		let id = mobile.get_id();
+		world.add_mobile(mobile,0,0);
+		assert_eq!(show_map(id,&mut world,Some(&"4".to_string())),"The map can be an odd number from 1 to 21 across.");
+		assert_eq!(show_map(id,&mut world,Some(&"23".to_string())),"The map can be an odd number from 1 to 21 across.");
+		// Three rows with you in the middle, then the key
+		let map = show_map(id,&mut world,Some(&"3".to_string()));
+		assert_eq!(map.lines().count(),4);
+		assert_eq!(map.lines().nth(1),Some(" @"));
+	}
+
+	#[test]
+	fn help_test()
+	{
+		let commands = CommandRegistry::standard();
+		let help = commands.help(None,false);
+		assert!(help.contains("\nlook [object]   Look at your surroundings, or at an object.\n"));
+		assert!(help.contains("\npassword        Change your password.\n"));
+		assert!(!help.contains("shutdown"));
+		assert!(commands.help(None,true).contains("shutdown"));
+		let help = commands.help(Some(&"inv".to_string()),false);
+		assert!(help.starts_with("inventory       List your inventory.\n"));
+		assert!(help.contains("Also: i\n"));
+		assert!(help.contains("Shortest: inv\n"));
+		assert_eq!(commands.help(Some(&"xyzzy".to_string()),false),"There is no help on that.");
+	}
+}
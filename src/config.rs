@@ -0,0 +1,303 @@
+use std::net::SocketAddr;
+use std::path::Path;
+
+// The file read at startup when no other is given
+pub const DEFAULT_CONFIG_FILE: &str = "pgmud.cfg";
+
+// Server settings. These come from a file of key,value lines and can
+// be overridden on the command line with --key value.
+#[derive(Clone)]
+pub struct Config
+{
+	// Where to listen for telnet and browser clients
+	pub telnet_address: String,
+	pub websocket_address: String,
+	// Tick in milliseconds
+	pub tick: u16,
+	// Sub ticks for interval between checking
+	// messages, getting input, and so forth
+	pub sub_tick: u16,
+	// Where characters and accounts are saved
+	pub save_directory: String,
+	// Wandering monsters arrive while the population density is below this
+	pub spawn_density: f32,
+	// Most commands a player can run in one tick. The rest wait.
+	pub commands_per_tick: u16,
+	// Seconds that a character stays in the world after losing its connection
+	pub link_dead_seconds: u16,
+	// Seconds that a client has to log in before it is disconnected
+	pub login_seconds: u16,
+	// Seconds between saves of the characters in play
+	pub save_seconds: u16,
+	// Seconds of warning that players get before the server stops
+	pub shutdown_seconds: u16,
+	// Characters who may run admin commands
+	pub admins: Vec<String>,
+	// Most sessions open at once, overall and from one address
+	pub max_sessions: u16,
+	pub max_sessions_per_address: u16,
+	// Most lines a session may send each second
+	pub commands_per_second: u16,
+	// How many locations away a shout can be heard
+	pub shout_radius: i16
+}
+
+impl Config
+{
+	pub fn new() -> Config
+	{
+		return Config
+		{
+			telnet_address: "127.0.0.1:7878".to_string(),
+			websocket_address: "127.0.0.1:7879".to_string(),
+			tick: 250,
+			sub_tick: 50,
+			save_directory: ".".to_string(),
+			spawn_density: 0.5,
+			commands_per_tick: 1,
+			link_dead_seconds: 60,
+			login_seconds: 60,
+			save_seconds: 60,
+			shutdown_seconds: 10,
+			admins: Vec::new(),
+			max_sessions: 100,
+			max_sessions_per_address: 5,
+			commands_per_second: 10,
+			shout_radius: 3
+		};
+	}
+
+	// Change one setting
+	pub fn set(&mut self, key: &str, value: &str) -> Result<(),String>
+	{
+		let bad_value = || { return "Bad value for ".to_string()+key+": "+value; };
+		match key
+		{
+			"telnet_address" => { self.telnet_address = value.to_string(); },
+			"websocket_address" => { self.websocket_address = value.to_string(); },
+			"tick" => { self.tick = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"sub_tick" => { self.sub_tick = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"save_directory" => { self.save_directory = value.to_string(); },
+			"spawn_density" => { self.spawn_density = value.parse::<f32>().map_err(|_| bad_value())?; },
+			"commands_per_tick" => { self.commands_per_tick = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"link_dead_seconds" => { self.link_dead_seconds = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"login_seconds" => { self.login_seconds = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"save_seconds" => { self.save_seconds = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"shutdown_seconds" => { self.shutdown_seconds = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"admins" => { self.admins = value.split_whitespace().map(|s| s.to_string()).collect(); },
+			"max_sessions" => { self.max_sessions = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"max_sessions_per_address" => { self.max_sessions_per_address = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"commands_per_second" => { self.commands_per_second = value.parse::<u16>().map_err(|_| bad_value())?; },
+			"shout_radius" => { self.shout_radius = value.parse::<i16>().map_err(|_| bad_value())?; },
+			_ => { return Err("Unknown setting: ".to_string()+key); }
+		}
+		return Ok(());
+	}
+
+	// Read settings from a file. Lines starting with # are comments.
+	pub fn load_from_file(&mut self, file_name: &str) -> Result<(),String>
+	{
+		let result = csv::ReaderBuilder::new()
+			.has_headers(false)
+			.comment(Some(b'#'))
+			.trim(csv::Trim::All)
+			.flexible(true)
+			.from_path(file_name);
+		let mut reader = match result
+		{
+			Ok(reader) => { reader },
+			Err(e) => { return Err("Could not read ".to_string()+file_name+": "+&e.to_string()); }
+		};
+		for item in reader.records()
+		{
+			match item
+			{
+				Ok(item) =>
+					{
+						if item.len() != 2
+						{
+							let line = item.position().map(|p| p.line()).unwrap_or(0);
+							return Err(file_name.to_string()+" line "+&line.to_string()+": expected key,value");
+						}
+						self.set(&item[0],&item[1])?;
+					},
+				Err(e) => { return Err("Could not read ".to_string()+file_name+": "+&e.to_string()); }
+			}
+		}
+		return Ok(());
+	}
+
+	// Check that the settings make sense together
+	pub fn validate(&self) -> Result<(),String>
+	{
+		if self.telnet_address.parse::<SocketAddr>().is_err()
+		{
+			return Err("telnet_address is not an address and port: ".to_string()+&self.telnet_address);
+		}
+		if self.websocket_address.parse::<SocketAddr>().is_err()
+		{
+			return Err("websocket_address is not an address and port: ".to_string()+&self.websocket_address);
+		}
+		if self.telnet_address == self.websocket_address
+		{
+			return Err("telnet_address and websocket_address must differ".to_string());
+		}
+		if self.tick == 0 || self.sub_tick == 0
+		{
+			return Err("tick and sub_tick must be more than zero".to_string());
+		}
+		if self.sub_tick > self.tick
+		{
+			return Err("sub_tick must not be longer than tick".to_string());
+		}
+		if !Path::new(&self.save_directory).is_dir()
+		{
+			return Err("save_directory is not a directory: ".to_string()+&self.save_directory);
+		}
+		if self.commands_per_tick == 0
+		{
+			return Err("commands_per_tick must be more than zero".to_string());
+		}
+		if self.login_seconds == 0
+		{
+			return Err("login_seconds must be more than zero".to_string());
+		}
+		if self.save_seconds == 0
+		{
+			return Err("save_seconds must be more than zero".to_string());
+		}
+		if self.max_sessions == 0 || self.max_sessions_per_address == 0
+		{
+			return Err("max_sessions and max_sessions_per_address must be more than zero".to_string());
+		}
+		if self.commands_per_second == 0
+		{
+			return Err("commands_per_second must be more than zero".to_string());
+		}
+		if self.shout_radius < 0
+		{
+			return Err("shout_radius must not be negative".to_string());
+		}
+		if self.spawn_density.is_nan() || self.spawn_density < 0.0
+		{
+			return Err("spawn_density must not be negative".to_string());
+		}
+		return Ok(());
+	}
+
+	pub fn is_admin(&self, name: &str) -> bool
+	{
+		return self.admins.iter().any(|admin| admin.eq_ignore_ascii_case(name));
+	}
+
+	// Build the configuration from the command line. The file given with
+	// --config is read first, then any --key value pairs override it.
+	pub fn from_args(args: &[String]) -> Result<Config,String>
+	{
+		let mut config = Config::new();
+		let mut file_name = None;
+		let mut overrides = Vec::new();
+		let mut i = 0;
+		while i < args.len()
+		{
+			let key = match args[i].strip_prefix("--")
+			{
+				Some(key) => { key.to_string() },
+				None => { return Err("Unexpected argument: ".to_string()+&args[i]); }
+			};
+			if i+1 >= args.len()
+			{
+				return Err("Missing value for --".to_string()+&key);
+			}
+			let value = args[i+1].clone();
+			if key == "config"
+			{
+				file_name = Some(value);
+			}
+			else
+			{
+				overrides.push((key,value));
+			}
+			i += 2;
+		}
+		match file_name
+		{
+			Some(file_name) => { config.load_from_file(&file_name)?; },
+			None =>
+				{
+					// The default file is optional
+					if Path::new(DEFAULT_CONFIG_FILE).exists()
+					{
+						config.load_from_file(DEFAULT_CONFIG_FILE)?;
+					}
+				}
+		}
+		for (key,value) in overrides
+		{
+			config.set(&key.replace('-',"_"),&value)?;
+		}
+		config.validate()?;
+		return Ok(config);
+	}
+}
+
+#[cfg(test)]
+mod config_unit_test
+{
+	use super::*;
+
+	fn args(line: &str) -> Vec<String>
+	{
+		return line.split_whitespace().map(|s| s.to_string()).collect();
+	}
+
+	#[test]
+	fn set_test()
+	{
+		let mut config = Config::new();
+		assert!(config.validate().is_ok());
+		assert!(config.set("tick","100").is_ok());
+		assert_eq!(config.tick,100);
+		assert!(config.set("tick","fast").is_err());
+		assert!(config.set("colour","blue").is_err());
+		assert!(config.set("sub_tick","200").is_ok());
+		assert!(config.validate().is_err());
+		let mut config = Config::new();
+		config.set("telnet_address","localhost").unwrap();
+		assert!(config.validate().is_err());
+		let mut config = Config::new();
+		config.set("save_directory","no such directory").unwrap();
+		assert!(config.validate().is_err());
+		let mut config = Config::new();
+		config.set("login_seconds","0").unwrap();
+		assert!(config.validate().is_err());
+		let mut config = Config::new();
+		config.set("save_seconds","0").unwrap();
+		assert!(config.validate().is_err());
+		let mut config = Config::new();
+		assert!(!config.is_admin("Jim"));
+		config.set("admins","Jim  Sally").unwrap();
+		assert!(config.is_admin("jim"));
+		assert!(config.is_admin("Sally"));
+		assert!(!config.is_admin("Fred"));
+	}
+
+	#[test]
+	fn load_config_test()
+	{
+		let file_name = "config_test.cfg";
+		std::fs::write(file_name,"# Staging\ntelnet_address, 0.0.0.0:4000\n\nspawn_density,0.25\n").unwrap();
+		let config = Config::from_args(&args("--config config_test.cfg --spawn-density 0.75"));
+		std::fs::write(file_name,"tick\n").unwrap();
+		let broken = Config::from_args(&args("--config config_test.cfg"));
+		let _ = std::fs::remove_file(file_name);
+		let config = config.unwrap();
+		assert_eq!(config.telnet_address,"0.0.0.0:4000");
+		assert_eq!(config.spawn_density,0.75);
+		assert_eq!(config.tick,250);
+		assert!(broken.is_err());
+		assert!(Config::from_args(&args("--config missing.cfg")).is_err());
+		assert!(Config::from_args(&args("--tick")).is_err());
+		assert!(Config::from_args(&args("tick 100")).is_err());
+	}
+}
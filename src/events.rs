@@ -86,6 +86,11 @@ impl Event for CombatEvent
 {
 	fn tick(&self, world: &mut WorldState, event_q: &mut EventList)
 	{
+		// Someone got away, so the fight is over
+		if !self.first_round && !world.is_fighting(self.attacker,self.defender)
+		{
+			return;
+		}
 		let a_location = world.find_mobile_location(self.attacker);
 		let b_location = world.find_mobile_location(self.defender);
 		if !a_location.is_some()
@@ -113,12 +118,16 @@ impl Event for CombatEvent
 			// Can the attacker see the defender?
 			if self.first_round && a.roll_perception() < b.roll_stealth()
 			{
-				world.message_list.broadcast(b.name_with_article.to_string()+" eludes "+&a.name_with_article.to_string()+"...",a_position.0,a_position.1);
+				world.message_list.broadcast("{y}".to_string()+&b.name_with_article+" eludes "+&a.name_with_article+"...{x}",a_position.0,a_position.1);
 				world.add_mobile(a,a_position.0,a_position.1);
 				world.add_mobile(b,b_position.0,b_position.1);
 				return;
 			}
-			// Go on to the combat
+			// Go on to the combat. There is no hiding in a fight.
+			a.fighting.insert(b.get_id());
+			b.fighting.insert(a.get_id());
+			a.stop_hiding();
+			b.stop_hiding();
 			let a_has_actions = a.use_action();
 			let b_has_actions = b.use_action();
 			if a_has_actions || b_has_actions
@@ -129,16 +138,17 @@ impl Event for CombatEvent
 					let damage = b.do_damage(a.damage_dice.roll());
 					if b.damage > b.max_hit_points()
 					{
-						world.message_list.broadcast(a.name_with_article.clone()+" slays "+&b.name_with_article+"!",a_position.0,a_position.1);	
-						world.message_list.post_for_target("You have been slain by ".to_string()+&a.name_with_article+"!",b.get_id());
+						world.message_list.broadcast("{R}".to_string()+&a.name_with_article+" slays "+&b.name_with_article+"!{x}",a_position.0,a_position.1);	
+						world.message_list.post_for_target("{R}You have been slain by ".to_string()+&a.name_with_article+"!{x}",b.get_id());
+						a.fighting.remove(&self.defender);
 						world.add_mobile(a,a_position.0,a_position.1);
-						b.is_killed();
+						b.is_killed(&world.save_directory);
 						world.add_corpse(&mut b,b_position.0,b_position.1);
 					}
 					else
 					{
-						world.message_list.broadcast(a.name_with_article.clone()+" wounds "+&b.name_with_article+" with a "+&a.wielded+
-							" for "+&damage.to_string()+"!",a_position.0,a_position.1);	
+						world.message_list.broadcast("{r}".to_string()+&a.name_with_article+" wounds "+&b.name_with_article+" with a "+&a.wielded+
+							" for {R}"+&damage.to_string()+"{r}!{x}",a_position.0,a_position.1);	
 						world.add_mobile(a,a_position.0,a_position.1);
 						world.add_mobile(b,b_position.0,b_position.1);
 						event_q.insert(Box::new(CombatEvent { attacker: self.attacker, defender: self.defender, first_round: false }));
@@ -149,16 +159,17 @@ impl Event for CombatEvent
 					let damage = a.do_damage(b.damage_dice.roll());
 					if a.damage > a.max_hit_points()
 					{
-						world.message_list.broadcast(b.name_with_article.clone()+" slays "+&a.name_with_article+"!",a_position.0,a_position.1);	
-						world.message_list.post_for_target("You have been slain by ".to_string()+&b.name_with_article+"!",a.get_id());
+						world.message_list.broadcast("{R}".to_string()+&b.name_with_article+" slays "+&a.name_with_article+"!{x}",a_position.0,a_position.1);	
+						world.message_list.post_for_target("{R}You have been slain by ".to_string()+&b.name_with_article+"!{x}",a.get_id());
+						b.fighting.remove(&self.attacker);
 						world.add_mobile(b,b_position.0,b_position.1);
-						a.is_killed();
+						a.is_killed(&world.save_directory);
 						world.add_corpse(&mut a,a_position.0,a_position.1);
 					}
 					else
 					{
-						world.message_list.broadcast(b.name_with_article.clone()+" wounds "+&a.name_with_article+" with a "+&b.wielded+	
-							" for "+&damage.to_string()+"!",a_position.0,a_position.1);	
+						world.message_list.broadcast("{r}".to_string()+&b.name_with_article+" wounds "+&a.name_with_article+" with a "+&b.wielded+	
+							" for {R}"+&damage.to_string()+"{r}!{x}",a_position.0,a_position.1);	
 						world.add_mobile(a,a_position.0,a_position.1);
 						world.add_mobile(b,b_position.0,b_position.1);
 						event_q.insert(Box::new(CombatEvent { attacker: self.attacker, defender: self.defender, first_round: false }));
@@ -166,14 +177,14 @@ impl Event for CombatEvent
 				}
 				else if outcome
 				{
-					world.message_list.broadcast(b.name_with_article.clone()+" repulses "+&a.name_with_article+"!",a_position.0,a_position.1);	
+					world.message_list.broadcast("{y}".to_string()+&b.name_with_article+" repulses "+&a.name_with_article+"!{x}",a_position.0,a_position.1);	
 					world.add_mobile(a,a_position.0,a_position.1);
 					world.add_mobile(b,b_position.0,b_position.1);
 					event_q.insert(Box::new(CombatEvent { attacker: self.attacker, defender: self.defender, first_round: false }));
 				}
 				else
 				{
-					world.message_list.broadcast(a.name_with_article.clone()+" repulses "+&b.name_with_article+"!",a_position.0,a_position.1);	
+					world.message_list.broadcast("{y}".to_string()+&a.name_with_article+" repulses "+&b.name_with_article+"!{x}",a_position.0,a_position.1);	
 					world.add_mobile(a,a_position.0,a_position.1);
 					world.add_mobile(b,b_position.0,b_position.1);
 					event_q.insert(Box::new(CombatEvent { attacker: self.attacker, defender: self.defender, first_round: false }));
@@ -194,7 +205,24 @@ impl Event for CombatEvent
 	}
 }
 
-// Move a mobile
+// The name of the direction of a step, such as "northeast"
+pub fn direction_name(dx: i16, dy: i16) -> Option<&'static str>
+{
+	match (dx,dy)
+	{
+		(0,1) => { return Some("north"); },
+		(1,1) => { return Some("northeast"); },
+		(1,0) => { return Some("east"); },
+		(1,-1) => { return Some("southeast"); },
+		(0,-1) => { return Some("south"); },
+		(-1,-1) => { return Some("southwest"); },
+		(-1,0) => { return Some("west"); },
+		(-1,1) => { return Some("northwest"); },
+		_ => { return None; }
+	}
+}
+
+// Move a mobile one step in any of the eight directions
 pub struct MoveMobileEvent
 {
 	pub uuid: usize,
@@ -206,6 +234,12 @@ impl Event for MoveMobileEvent
 {
 	fn tick(&self, world: &mut WorldState, _: &mut EventList)
 	{
+		// Where we go to and where we come from
+		let (leave_direction, arrive_direction) = match (direction_name(self.dx,self.dy),direction_name(-self.dx,-self.dy))
+		{
+			(Some(leave),Some(arrive)) => { (leave,arrive) },
+			_ => { return; }
+		};
 		let coordinate = world.find_mobile_location(self.uuid);
 		if !coordinate.is_some()
 		{
@@ -215,44 +249,199 @@ impl Event for MoveMobileEvent
 		match mobile
 		{
 			None => { return; }
-			Some(mobile) => 
+			Some(mut mobile) => 
 				{
 					let xy = coordinate.unwrap();
-					let id = mobile.get_id();
-					let arrive_prefix = mobile.arrive_prefix.clone();
-					let leave_prefix = mobile.leave_prefix.clone();
-					world.add_mobile(mobile,xy.0+self.dx,xy.1+self.dy);
-					let location_description = world.get_location_description(xy.0+self.dx,xy.1+self.dy);
-					match (self.dx,self.dy)
+					if held_in_fight(world,&mut mobile,xy)
 					{
-						(0,1) =>
-						{
-							world.message_list.post_no_echo(arrive_prefix+" from the south.",xy.0+self.dx,xy.1+self.dy,id);
-							world.message_list.post_no_echo(leave_prefix+" to the north.",xy.0,xy.1,id);
-							world.message_list.post_for_target(location_description,id);
-						}
-						(0,-1) =>
-						{
-							world.message_list.post_no_echo(arrive_prefix+" from the north.",xy.0+self.dx,xy.1+self.dy,id);
-							world.message_list.post_no_echo(leave_prefix+" to the south.",xy.0,xy.1,id);
-							world.message_list.post_for_target(location_description,id);
-						}
-						(1,0) =>
-						{
-							world.message_list.post_no_echo(arrive_prefix+" from the west.",xy.0+self.dx,xy.1+self.dy,id);
-							world.message_list.post_no_echo(leave_prefix+" to the east.",xy.0,xy.1,id);
-							world.message_list.post_for_target(location_description,id);
-						}
-						(-1,0) =>
-						{
-							world.message_list.post_no_echo(arrive_prefix+" from the east.",xy.0+self.dx,xy.1+self.dy,id);
-							world.message_list.post_no_echo(leave_prefix+" to the west.",xy.0,xy.1,id);
-							world.message_list.post_for_target(location_description,id);
-						}
-						_ => { return; }
+						world.add_mobile(mobile,xy.0,xy.1);
+						return;
+					}
+					let arrive_message = mobile.arrive_prefix.clone()+" from the "+arrive_direction+".";
+					let leave_message = mobile.leave_prefix.clone()+" to the "+leave_direction+".";
+					travel(world,mobile,xy,(xy.0+self.dx,xy.1+self.dy),leave_message,arrive_message);
 				}
+		}
+	}
+}
+
+// You can't just walk away from a fight. A fight with someone who has
+// gone is forgotten.
+fn held_in_fight(world: &mut WorldState, mobile: &mut Box<Mobile>, xy: (i16,i16)) -> bool
+{
+	mobile.fighting.retain(|opponent| world.find_mobile_location(*opponent) == Some(xy));
+	if !mobile.fighting.is_empty()
+	{
+		world.message_list.post_for_target("You are fighting! You will have to flee.".to_string(),mobile.get_id());
+		return true;
+	}
+	return false;
+}
+
+// Put a mobile in its new place, tell those who see it come and go,
+// and show it where it is now
+fn travel(world: &mut WorldState, mobile: Box<Mobile>, from: (i16,i16), to: (i16,i16), leave_message: String, arrive_message: String)
+{
+	let id = mobile.get_id();
+	if mobile.sneaking
+	{
+		// Only those who notice see the sneak come and go
+		for observer in world.noticed_by(to.0,to.1,&mobile)
+		{
+			world.message_list.post_for_target(arrive_message.clone(),observer);
+		}
+		for observer in world.noticed_by(from.0,from.1,&mobile)
+		{
+			world.message_list.post_for_target(leave_message.clone(),observer);
+		}
+		world.add_mobile(mobile,to.0,to.1);
+	}
+	else
+	{
+		world.add_mobile(mobile,to.0,to.1);
+		world.message_list.post_no_echo(arrive_message,to.0,to.1,id);
+		world.message_list.post_no_echo(leave_message,from.0,from.1,id);
+	}
+	world.look_for_hidden(to.0,to.1,id);
+	let location_description = world.get_location_description(to.0,to.1,id);
+	world.message_list.post_for_target(location_description,id);
+}
+
+// Go up or down, through an exit such as a tunnel
+pub struct UseExitEvent
+{
+	pub uuid: usize,
+	pub direction: &'static str
+}
+
+impl Event for UseExitEvent
+{
+	fn tick(&self, world: &mut WorldState, _: &mut EventList)
+	{
+		let xy = world.find_mobile_location(self.uuid);
+		if xy.is_none()
+		{
+			return;
+		}
+		let xy = xy.unwrap();
+		let mut mobile = world.fetch_mobile(self.uuid).unwrap();
+		if held_in_fight(world,&mut mobile,xy)
+		{
+			world.add_mobile(mobile,xy.0,xy.1);
+			return;
+		}
+		let to = world.find_exit(xy.0,xy.1,self.direction,self.uuid);
+		if to.is_none()
+		{
+			world.message_list.post_for_target("There is no way ".to_string()+self.direction+" here.",self.uuid);
+			world.add_mobile(mobile,xy.0,xy.1);
+			return;
+		}
+		let arrive_message = mobile.arrive_prefix.clone()+" from "+if self.direction == "up" { "below" } else { "above" }+".";
+		let leave_message = mobile.leave_prefix.clone()+" "+self.direction+"wards.";
+		travel(world,mobile,xy,to.unwrap(),leave_message,arrive_message);
+	}
+}
+
+// One of the eight directions, as a change in x and y
+fn random_direction() -> (i16,i16)
+{
+	let die = Dice { number: 1, die: 8 };
+	match die.roll()
+	{
+		1 => { return (0,1); },
+		2 => { return (1,1); },
+		3 => { return (1,0); },
+		4 => { return (1,-1); },
+		5 => { return (0,-1); },
+		6 => { return (-1,-1); },
+		7 => { return (-1,0); },
+		_ => { return (-1,1); }
+	}
+}
+
+// Try to get away from a fight
+pub struct FleeEvent
+{
+	pub uuid: usize
+}
+
+impl Event for FleeEvent
+{
+	fn tick(&self, world: &mut WorldState, event_q: &mut EventList)
+	{
+		let position = world.find_mobile_location(self.uuid);
+		if position.is_none()
+		{
+			return;
+		}
+		let position = position.unwrap();
+		let mut mobile = world.fetch_mobile(self.uuid).unwrap();
+		mobile.fighting.retain(|opponent| world.find_mobile_location(*opponent) == Some(position));
+		if mobile.fighting.is_empty()
+		{
+			world.message_list.post_for_target("You aren't fighting anyone.".to_string(),self.uuid);
+			world.add_mobile(mobile,position.0,position.1);
+			return;
+		}
+		// Fleeing takes the turn whether or not it works. The fight may
+		// already have used the turn up, so that can't stop the escape.
+		let stealth = mobile.roll_stealth();
+		mobile.use_action();
+		// Every opponent has a chance to see the escape coming, and the
+		// first one who does gets a free attack
+		let mut chaser: Option<Box<Mobile> > = None;
+		let mut names = Vec::new();
+		for opponent_id in mobile.fighting.clone()
+		{
+			let opponent = world.fetch_mobile(opponent_id).unwrap();
+			names.push(opponent.name_with_article.clone());
+			if chaser.is_none() && opponent.roll_perception() >= stealth
+			{
+				chaser = Some(opponent);
+			}
+			else
+			{
+				world.add_mobile(opponent,position.0,position.1);
 			}
 		}
+		if chaser.is_none()
+		{
+			let direction = random_direction();
+			for opponent_id in mobile.fighting.clone()
+			{
+				let mut opponent = world.fetch_mobile(opponent_id).unwrap();
+				opponent.fighting.remove(&self.uuid);
+				world.add_mobile(opponent,position.0,position.1);
+			}
+			mobile.fighting.clear();
+			let names = names.join(" and ");
+			world.message_list.post_for_target("{y}You flee from ".to_string()+&names+"!{x}",self.uuid);
+			world.message_list.post_no_echo("{y}".to_string()+&mobile.name_with_article+" flees from "+&names+"!{x}",position.0,position.1,self.uuid);
+			world.add_mobile(mobile,position.0,position.1);
+			MoveMobileEvent { uuid: self.uuid, dx: direction.0, dy: direction.1 }.tick(world,event_q);
+			return;
+		}
+		let mut opponent = chaser.unwrap();
+		// The opponent gets a free attack
+		world.message_list.post_for_target("{y}You fail to get away!{x}".to_string(),self.uuid);
+		let damage = mobile.do_damage(opponent.damage_dice.roll());
+		if mobile.damage > mobile.max_hit_points()
+		{
+			world.message_list.broadcast("{R}".to_string()+&opponent.name_with_article+" slays "+&mobile.name_with_article+"!{x}",position.0,position.1);
+			world.message_list.post_for_target("{R}You have been slain by ".to_string()+&opponent.name_with_article+"!{x}",self.uuid);
+			opponent.fighting.remove(&self.uuid);
+			world.add_mobile(opponent,position.0,position.1);
+			mobile.is_killed(&world.save_directory);
+			world.add_corpse(&mut mobile,position.0,position.1);
+		}
+		else
+		{
+			world.message_list.broadcast("{r}".to_string()+&opponent.name_with_article+" wounds "+&mobile.name_with_article+" with a "+&opponent.wielded+
+				" for {R}"+&damage.to_string()+"{r}!{x}",position.0,position.1);
+			world.add_mobile(opponent,position.0,position.1);
+			world.add_mobile(mobile,position.0,position.1);
+		}
 	}
 }
 
@@ -288,7 +477,7 @@ impl Event for ActiveMonsterEvent
 				{
 					if target.roll_stealth() > mobile.roll_perception()
 					{
-						world.message_list.broadcast(mobile.name_with_article.clone()+" attacks "+&target.name_with_article+"!",xy.0,xy.1);	
+						world.message_list.broadcast("{r}".to_string()+&mobile.name_with_article+" attacks "+&target.name_with_article+"!{x}",xy.0,xy.1);	
 						event_q.insert(Box::new(CombatEvent { attacker: self.id, defender: target.get_id(), first_round: true }));
 					}
 				}
@@ -299,16 +488,9 @@ impl Event for ActiveMonsterEvent
 		if mobile.wanders
 		{
 			let die = Dice { number: 1 , die: 100 };
-			let direction = match die.roll()
-					{
-						1 => { (1,0) },
-						2 => { (-1,0) },
-						3 => { (0,-1) },
-						4 => { (0,1) },
-						_ => { (0,0) }
-					};
-			if direction != (0,0) 
+			if die.roll() <= 4
 			{
+				let direction = random_direction();
 				let next_location_type = world.get_location_type(xy.0+direction.0,xy.1+direction.1);
 				let current_location_type = world.get_location_type(xy.0,xy.1);
 				if current_location_type == next_location_type
@@ -323,10 +505,10 @@ impl Event for ActiveMonsterEvent
 			if item.is_some()
 			{
 				let item = item.unwrap();
-				if mobile.has_room_for_item(&item)
+				if mobile.has_room_for_item()
 				{
 					let mob_name = mobile.name_with_article.clone();
-					world.message_list.broadcast(mob_name+&" picks up a ".to_string()+&item.get_name(),xy.0,xy.1);
+					world.message_list.broadcast(mob_name+&" picks up a {y}".to_string()+&item.get_name()+"{x}",xy.0,xy.1);
 					mobile.add_item(item,false);
 				}
 				else
@@ -453,6 +635,8 @@ impl WanderingMonsterLocationVisitor
 
 pub struct WanderingMonsterEvent
 {
+	// Monsters only arrive while the population density is below this
+	density: f32
 }
 
 impl Event for WanderingMonsterEvent
@@ -460,7 +644,7 @@ impl Event for WanderingMonsterEvent
 	fn tick(&self, world: &mut WorldState, event_q: &mut EventList)
 	{
 		print!("density = {}",world.population_density());
-		if world.population_density() < 0.5
+		if world.population_density() < self.density
 		{
 			let mut visitor = WanderingMonsterLocationVisitor { monster_list: LinkedList::new() };
 			world.visit_all_locations(&mut visitor);
@@ -476,16 +660,16 @@ impl Event for WanderingMonsterEvent
 				world.add_mobile(item.2,item.0,item.1);
 			}
 		}
-		let next_event = Box::new(WanderingMonsterEvent::new());
+		let next_event = Box::new(WanderingMonsterEvent::new(self.density));
 		event_q.insert(next_event);
 	}
 }
 
 impl WanderingMonsterEvent
 {
-	pub fn new() -> WanderingMonsterEvent
+	pub fn new(density: f32) -> WanderingMonsterEvent
 	{
-		return WanderingMonsterEvent {};
+		return WanderingMonsterEvent { density: density };
 	}
 }
 
@@ -570,14 +754,14 @@ impl Event for StealEvent
 					if item.is_some()
 					{
 						let item = item.unwrap();
-						if a.has_room_for_item(&item)
+						if a.has_room_for_item()
 						{
-							world.message_list.post_for_target("You stole a ".to_string()+&item.get_name(),a.get_id());
+							world.message_list.post_for_target("You stole a {y}".to_string()+&item.get_name()+"{x}",a.get_id());
 							a.add_item(item,true);
 						}
 						else
 						{
-							world.message_list.post_for_target("You don't have room for ".to_string()+&item.get_name()+"!",a.get_id());
+							world.message_list.post_for_target("You don't have room for {y}".to_string()+&item.get_name()+"{x}!",a.get_id());
 							b.add_item(item,false);
 						}
 					}
@@ -839,4 +1023,182 @@ mod events_unit_test
 		make_metal_item_test(ItemTypeCode::Pick);
 	}
 
+	#[test]
+	fn move_diagonal_test()
+	{
+		let mut event_q = EventList::new();
+		let mut world = WorldState::new();
+		let mobile = Mobile::rabbit();
+		let id = mobile.get_id();
+		let arrive_prefix = mobile.arrive_prefix.clone();
+		let leave_prefix = mobile.leave_prefix.clone();
+		world.add_mobile(mobile,0,0);
+		let before = std::time::SystemTime::now();
+		MoveMobileEvent { uuid: id, dx: 1, dy: -1 }.tick(&mut world,&mut event_q);
+		assert_eq!(world.find_mobile_location(id),Some((1,-1)));
+		let reader = crate::uid::new();
+		assert_eq!(world.message_list.read(1,-1,reader,before),arrive_prefix+" from the northwest.\n");
+		assert_eq!(world.message_list.read(0,0,reader,before),leave_prefix+" to the southeast.\n");
+		// Anything but a single step goes nowhere
+		MoveMobileEvent { uuid: id, dx: 2, dy: 0 }.tick(&mut world,&mut event_q);
+		MoveMobileEvent { uuid: id, dx: 0, dy: 0 }.tick(&mut world,&mut event_q);
+		assert_eq!(world.find_mobile_location(id),Some((1,-1)));
+		assert_eq!(direction_name(-1,1),Some("northwest"));
+		assert_eq!(direction_name(0,2),None);
+	}
+
+	#[test]
+	fn sneak_test()
+	{
+		let mut event_q = EventList::new();
+		let mut world = WorldState::new();
+		let mut rabbit = Mobile::rabbit();
+		rabbit.sneaking = true;
+		let id = rabbit.get_id();
+		let mut sharp = Mobile::new_character(&"Sharp".to_string());
+		sharp.perception = 100;
+		let sharp_id = sharp.get_id();
+		let mut dull = Mobile::new_character(&"Dull".to_string());
+		dull.perception = -100;
+		let dull_id = dull.get_id();
+		world.add_mobile(rabbit,0,0);
+		world.add_mobile(sharp,1,0);
+		world.add_mobile(dull,1,0);
+		let before = std::time::SystemTime::now();
+		MoveMobileEvent { uuid: id, dx: 1, dy: 0 }.tick(&mut world,&mut event_q);
+		assert_eq!(world.find_mobile_location(id),Some((1,0)));
+		assert!(world.message_list.read_targetted(sharp_id,before).contains("from the west."));
+		assert!(world.message_list.read_targetted(dull_id,before).is_empty());
+		assert!(!world.message_list.read(1,0,dull_id,before).contains("from the west."));
+	}
+
+	#[test]
+	fn use_exit_test()
+	{
+		let mut event_q = EventList::new();
+		let mut world = WorldState::new();
+		let mobile = Mobile::new_character(&"Jim".to_string());
+		let id = mobile.get_id();
+		world.add_mobile(mobile,0,0);
+		let before = std::time::SystemTime::now();
+		UseExitEvent { uuid: id, direction: "down" }.tick(&mut world,&mut event_q);
+		assert_eq!(world.find_mobile_location(id),Some((0,0)));
+		assert!(world.message_list.read_targetted(id,before).contains("There is no way down here."));
+	}
+
+	#[test]
+	fn flee_test()
+	{
+		let mut event_q = EventList::new();
+		let mut world = WorldState::new();
+		let mut mobile = Mobile::new_character(&"Jim".to_string());
+		let mut rodent = Mobile::rodent();
+		let id = mobile.get_id();
+		let rodent_id = rodent.get_id();
+		mobile.fighting.insert(rodent_id);
+		rodent.fighting.insert(id);
+		mobile.stealth = -100;
+		mobile.max_damage = 100;
+		world.add_mobile(mobile,0,0);
+		world.add_mobile(rodent,0,0);
+		// No walking away
+		MoveMobileEvent { uuid: id, dx: 1, dy: 0 }.tick(&mut world,&mut event_q);
+		assert_eq!(world.find_mobile_location(id),Some((0,0)));
+		// A failed flee gives the rodent a free bite
+		FleeEvent { uuid: id }.tick(&mut world,&mut event_q);
+		assert_eq!(world.find_mobile_location(id),Some((0,0)));
+		let mut mobile = world.fetch_mobile(id).unwrap();
+		assert_eq!(mobile.damage,1);
+		mobile.stealth = 100;
+		world.add_mobile(mobile,0,0);
+		assert!(world.is_fighting(rodent_id,id));
+		FleeEvent { uuid: id }.tick(&mut world,&mut event_q);
+		assert!(world.find_mobile_location(id) != Some((0,0)));
+		assert!(!world.is_fighting(rodent_id,id));
+		// The rest of the fight does nothing
+		CombatEvent { attacker: rodent_id, defender: id, first_round: false }.tick(&mut world,&mut event_q);
+		assert_eq!(world.fetch_mobile(id).unwrap().damage,1);
+	}
+
+	#[test]
+	fn random_direction_test()
+	{
+		let mut seen = std::collections::BTreeSet::new();
+		for _ in 0..1000
+		{
+			let direction = random_direction();
+			assert!(direction != (0,0));
+			assert!(direction.0.abs() <= 1 && direction.1.abs() <= 1);
+			seen.insert(direction);
+		}
+		assert_eq!(seen.len(),8);
+	}
+
+	#[test]
+	fn flee_live_fight_test()
+	{
+		let mut event_q = EventList::new();
+		let mut world = WorldState::new();
+		let mut mobile = Mobile::new_character(&"Jim".to_string());
+		let mut rodent = Mobile::rodent();
+		let id = mobile.get_id();
+		let rodent_id = rodent.get_id();
+		mobile.stealth = 100;
+		mobile.max_damage = 100;
+		mobile.fighting.insert(rodent_id);
+		rodent.fighting.insert(id);
+		rodent.perception = -100;
+		rodent.max_damage = 100;
+		world.add_mobile(mobile,0,0);
+		world.add_mobile(rodent,0,0);
+		// The round of combat runs first and spends both fighters' actions
+		event_q.insert(Box::new(CombatEvent { attacker: id, defender: rodent_id, first_round: false }));
+		event_q.insert(Box::new(FleeEvent { uuid: id }));
+		event_q.tick(&mut world);
+		assert!(world.find_mobile_location(id) != Some((0,0)));
+		assert!(!world.is_fighting(rodent_id,id));
+	}
+
+	#[test]
+	fn two_attackers_test()
+	{
+		let mut event_q = EventList::new();
+		let mut world = WorldState::new();
+		let mut mobile = Mobile::new_character(&"Jim".to_string());
+		let mut rodent = Mobile::rodent();
+		let mut second_rodent = Mobile::rodent();
+		let id = mobile.get_id();
+		let rodent_id = rodent.get_id();
+		let second_id = second_rodent.get_id();
+		mobile.fighting.insert(rodent_id);
+		rodent.fighting.insert(id);
+		mobile.stealth = -100;
+		mobile.max_damage = 100;
+		second_rodent.max_damage = 100;
+		world.add_mobile(mobile,0,0);
+		world.add_mobile(rodent,0,0);
+		world.add_mobile(second_rodent,0,0);
+		// Joining in doesn't end the first fight
+		CombatEvent { attacker: second_id, defender: id, first_round: true }.tick(&mut world,&mut event_q);
+		assert!(world.is_fighting(id,rodent_id));
+		assert!(world.is_fighting(rodent_id,id));
+		assert!(world.is_fighting(id,second_id));
+		assert!(world.is_fighting(second_id,id));
+		// Getting away means getting away from both
+		let mut mobile = world.fetch_mobile(id).unwrap();
+		mobile.stealth = 100;
+		world.add_mobile(mobile,0,0);
+		for opponent_id in [rodent_id,second_id]
+		{
+			let mut opponent = world.fetch_mobile(opponent_id).unwrap();
+			opponent.perception = -100;
+			world.add_mobile(opponent,0,0);
+		}
+		FleeEvent { uuid: id }.tick(&mut world,&mut event_q);
+		assert!(world.find_mobile_location(id) != Some((0,0)));
+		assert!(!world.is_fighting(rodent_id,id));
+		assert!(!world.is_fighting(second_id,id));
+		assert!(world.fetch_mobile(rodent_id).unwrap().fighting.is_empty());
+	}
+
 }
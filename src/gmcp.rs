@@ -0,0 +1,137 @@
+// GMCP messages for clients. Each message is a package name followed
+// by a JSON value, so that gauges and mappers don't have to scrape text.
+use crate::items::*;
+use crate::location::LocationTypeCode;
+use crate::mobile::Mobile;
+use crate::object::Object;
+
+// Quote a string for JSON
+pub fn json_string(text: &str) -> String
+{
+	let mut result = "\"".to_string();
+	for c in text.chars()
+	{
+		match c
+		{
+			'"' => { result += "\\\""; },
+			'\\' => { result += "\\\\"; },
+			'\n' => { result += "\\n"; },
+			'\r' => { result += "\\r"; },
+			'\t' => { result += "\\t"; },
+			c if (c as u32) < 0x20 => { result += &format!("\\u{:04x}",c as u32); },
+			c => { result.push(c); }
+		}
+	}
+	result.push('"');
+	return result;
+}
+
+// Build a JSON object from fields whose values are already JSON
+fn json_object(fields: &[(&str,String)]) -> String
+{
+	let mut result = "{".to_string();
+	for (i,(key,value)) in fields.iter().enumerate()
+	{
+		if i > 0 { result.push(','); }
+		result += &(json_string(key)+":"+value);
+	}
+	result.push('}');
+	return result;
+}
+
+pub fn char_vitals(mobile: &Mobile) -> String
+{
+	let fields = [
+		("hp",(mobile.max_hit_points()-mobile.damage).to_string()),
+		("maxhp",mobile.max_hit_points().to_string()),
+		("xp",mobile.xp.to_string())
+	];
+	return "Char.Vitals ".to_string()+&json_object(&fields);
+}
+
+pub fn char_stats(mobile: &Mobile) -> String
+{
+	let fields = [
+		("str",mobile.strength.to_string()),
+		("dex",mobile.dexterity.to_string()),
+		("con",mobile.constitution.to_string()),
+		("int",mobile.intelligence.to_string()),
+		("wis",mobile.wisdom.to_string()),
+		("chr",mobile.charisma.to_string()),
+		("luck",mobile.luck.to_string()),
+		("combat",mobile.combat.to_string()),
+		("steal",mobile.steal.to_string()),
+		("perception",mobile.perception.to_string()),
+		("leatherwork",mobile.leatherwork.to_string()),
+		("metalwork",mobile.metalwork.to_string()),
+		("woodcraft",mobile.woodcraft.to_string()),
+		("knowledge",mobile.knowledge.to_string()),
+		("stealth",mobile.stealth.to_string()),
+		("armor",mobile.armor.to_string()),
+		("wielded",json_string(&mobile.wielded))
+	];
+	return "Char.Stats ".to_string()+&json_object(&fields);
+}
+
+pub fn room_info(x: i16, y: i16, location_type: LocationTypeCode) -> String
+{
+	let fields = [
+		("x",x.to_string()),
+		("y",y.to_string()),
+		("type",json_string(location_type.name()))
+	];
+	return "Room.Info ".to_string()+&json_object(&fields);
+}
+
+pub fn char_items(mobile: &Mobile) -> String
+{
+	let mut items = "[".to_string();
+	for (i,item) in mobile.inventory.iter().enumerate()
+	{
+		if i > 0 { items.push(','); }
+		let category = match item.category_code
+		{
+			ItemCategoryCode::Misc => { "misc" },
+			ItemCategoryCode::Weapon => { "weapon" },
+			ItemCategoryCode::Armor => { "armor" }
+		};
+		items += &json_object(&[("name",json_string(&item.get_name())),("category",json_string(category))]);
+	}
+	items.push(']');
+	let fields = [
+		("location",json_string("inv")),
+		("items",items)
+	];
+	return "Char.Items.List ".to_string()+&json_object(&fields);
+}
+
+#[cfg(test)]
+mod gmcp_unit_test
+{
+	use super::*;
+
+	#[test]
+	fn json_test()
+	{
+		assert_eq!(json_string("say \"hi\"\n"),"\"say \\\"hi\\\"\\n\"");
+		assert_eq!(json_string("\u{1b}"),"\"\\u001b\"");
+		assert_eq!(room_info(-1,2,LocationTypeCode::DeepWoods),"Room.Info {\"x\":-1,\"y\":2,\"type\":\"Deep Woods\"}");
+	}
+
+	#[test]
+	fn char_test()
+	{
+		let mut mobile = Mobile::new_character(&"Jim".to_string());
+		mobile.damage = 1;
+		let hp = mobile.max_hit_points()-1;
+		assert!(char_vitals(&mobile).starts_with(&("Char.Vitals {\"hp\":".to_string()+&hp.to_string()+",")));
+		mobile.inventory.clear();
+		assert_eq!(char_items(&mobile),"Char.Items.List {\"location\":\"inv\",\"items\":[]}");
+		mobile.add_item(Item::sword(),true);
+		assert!(char_items(&mobile).contains("{\"name\":\"sword\",\"category\":\"weapon\"}"));
+		assert!(char_stats(&mobile).contains("\"wielded\":\"fist\""));
+		mobile.wield_item_by_name(&"sword".to_string());
+		assert_eq!(char_items(&mobile),"Char.Items.List {\"location\":\"inv\",\"items\":[]}");
+		assert!(char_stats(&mobile).contains("\"wielded\":\"sword\""));
+	}
+}
@@ -1,6 +1,7 @@
 use crate::object::Object;
 use crate::mobile::*;
 use crate::dice::*;
+use std::collections::BTreeSet;
 
 #[derive(Copy,Clone,PartialEq)]
 pub enum ItemTypeCode
@@ -29,7 +30,7 @@ pub enum ItemTypeCode
 	HealingPotion
 }
 
-#[derive(Copy,Clone)]
+#[derive(Copy,Clone,PartialEq)]
 pub enum ItemCategoryCode
 {
 	Misc,
@@ -54,6 +55,10 @@ pub struct Item
 	pub frequency: i16,
 	// Armor protection provided if this is armor?
 	pub armor_value: i16,
+	// How hard it is to find, or 0 if it is in plain sight
+	pub hidden: i16,
+	// Those who have found it while it was hidden
+	pub found_by: BTreeSet<usize>,
 }
 
 impl Object for Item
@@ -83,6 +88,16 @@ impl Object for Item
 	}
 }
 
+// "a" or "an", whichever goes in front of the name
+pub fn indefinite_article(name: &str) -> &'static str
+{
+	match name.chars().next().map(|first| first.to_ascii_lowercase())
+	{
+		Some('a') | Some('e') | Some('i') | Some('o') | Some('u') => { return "an"; },
+		_ => { return "a"; }
+	}
+}
+
 impl Item
 {
 
@@ -103,6 +118,9 @@ impl Item
 
 	pub fn got_item(&mut self, mobile: &mut Mobile, take_xp: bool)
 	{
+		// Once it has been picked up it is no longer hidden
+		self.hidden = 0;
+		self.found_by.clear();
 		if take_xp && !self.xp_in_town_only
 		{
 			mobile.xp += self.xp_value;
@@ -114,15 +132,29 @@ impl Item
 			ItemTypeCode::DwarfBeard => { mobile.luck -= 1; }
 			ItemTypeCode::RabbitFoot => { mobile.luck += 1; }
 			ItemTypeCode::GreenPenny => { mobile.luck += 1; }
-			ItemTypeCode::Sword => { mobile.wielded = self.name.clone(); mobile.damage_dice = Dice { number: 1, die: 8}; }
-			ItemTypeCode::Pick => { mobile.wielded = self.name.clone(); mobile.damage_dice = Dice { number: 1, die: 6}; }
-			ItemTypeCode::PointedStick => { mobile.wielded = self.name.clone(); mobile.damage_dice = Dice { number: 1, die: 3}; }
-			ItemTypeCode::Axe => { mobile.wielded = self.name.clone(); mobile.damage_dice = Dice { number: 1, die: 8}; }
-			ItemTypeCode::StoneKnife => { mobile.wielded = self.name.clone(); mobile.damage_dice = Dice { number: 1, die: 4}; }
 			_ => { return; }
 		}
 	}
 
+	pub fn is_visible_to(&self, uuid: usize) -> bool
+	{
+		return self.hidden == 0 || self.found_by.contains(&uuid);
+	}
+
+	// Damage done with this item when it is wielded
+	pub fn weapon_dice(&self) -> Dice
+	{
+		match self.type_code
+		{
+			ItemTypeCode::Sword => { return Dice { number: 1, die: 8}; }
+			ItemTypeCode::Pick => { return Dice { number: 1, die: 6}; }
+			ItemTypeCode::PointedStick => { return Dice { number: 1, die: 3}; }
+			ItemTypeCode::Axe => { return Dice { number: 1, die: 8}; }
+			ItemTypeCode::StoneKnife => { return Dice { number: 1, die: 4}; }
+			_ => { return Dice { number: 1, die: 2}; }
+		}
+	}
+
 	pub fn drop_item(&mut self, mobile: &mut Mobile)
 	{
 		match self.type_code
@@ -137,9 +169,75 @@ impl Item
 
 	pub fn tick(&mut self)
 	{
+		// A hidden item keeps until someone takes it
+		if self.hidden > 0
+		{
+			return;
+		}
 		self.lifetime -= 1;
 	}
 
+	// The name of the item type for saving
+	pub fn type_name(&self) -> &str
+	{
+		match self.type_code
+		{
+			ItemTypeCode::UncutGemstone => { return "UncutGemstone"; },
+			ItemTypeCode::UselessRock => { return "UselessRock"; },
+			ItemTypeCode::HealthyNutsAndSeeds => { return "HealthyNutsAndSeeds"; },
+			ItemTypeCode::MetalIngot => { return "MetalIngot"; },
+			ItemTypeCode::DwarfBeard => { return "DwarfBeard"; },
+			ItemTypeCode::RabbitFoot => { return "RabbitFoot"; },
+			ItemTypeCode::GreenPenny => { return "GreenPenny"; },
+			ItemTypeCode::ForestDebris => { return "ForestDebris"; },
+			ItemTypeCode::Corpse => { return "Corpse"; },
+			ItemTypeCode::Sword => { return "Sword"; },
+			ItemTypeCode::Pick => { return "Pick"; },
+			ItemTypeCode::Axe => { return "Axe"; },
+			ItemTypeCode::PointedStick => { return "PointedStick"; },
+			ItemTypeCode::Rawhide => { return "Rawhide"; },
+			ItemTypeCode::LeatherArmor => { return "LeatherArmor"; },
+			ItemTypeCode::ChainArmor => { return "ChainArmor"; },
+			ItemTypeCode::HideArmor => { return "HideArmor"; },
+			ItemTypeCode::BoneJewelry => { return "BoneJewelry"; },
+			ItemTypeCode::GoldBauble => { return "GoldBauble"; },
+			ItemTypeCode::ShrunkenHead => { return "ShrunkenHead"; },
+			ItemTypeCode::StoneKnife => { return "StoneKnife"; },
+			ItemTypeCode::HealingPotion => { return "HealingPotion"; }
+		}
+	}
+
+	// Make a new item of the named type
+	pub fn from_type_name(type_name: &str) -> Option<Box<Item> >
+	{
+		match type_name
+		{
+			"UncutGemstone" => { return Some(Item::uncut_precious_stone()); },
+			"UselessRock" => { return Some(Item::useless_rock()); },
+			"HealthyNutsAndSeeds" => { return Some(Item::healthy_nuts_and_seeds()); },
+			"MetalIngot" => { return Some(Item::metal_ingot()); },
+			"DwarfBeard" => { return Some(Item::dwarf_beard()); },
+			"RabbitFoot" => { return Some(Item::rabbit_foot()); },
+			"GreenPenny" => { return Some(Item::green_penny()); },
+			"ForestDebris" => { return Some(Item::forest_debris()); },
+			"Corpse" => { return Some(Item::corpse(String::new())); },
+			"Sword" => { return Some(Item::sword()); },
+			"Pick" => { return Some(Item::pick()); },
+			"Axe" => { return Some(Item::axe()); },
+			"PointedStick" => { return Some(Item::pointed_stick()); },
+			"Rawhide" => { return Some(Item::rawhide()); },
+			"LeatherArmor" => { return Some(Item::leather_armor()); },
+			"ChainArmor" => { return Some(Item::chainmail()); },
+			"HideArmor" => { return Some(Item::hide_armor()); },
+			"BoneJewelry" => { return Some(Item::bone_jewelry()); },
+			"GoldBauble" => { return Some(Item::gold_bauble()); },
+			"ShrunkenHead" => { return Some(Item::shrunken_head()); },
+			"StoneKnife" => { return Some(Item::stone_knife()); },
+			"HealingPotion" => { return Some(Item::healing_potion()); },
+			_ => { return None; }
+		}
+	}
+
 	pub fn basic_item(type_code: ItemTypeCode, cat_code: ItemCategoryCode) -> Box<Item>
 	{
 		return Box::new(
@@ -155,6 +253,8 @@ impl Item
 				lifetime: 100,
 				armor_value: 0,
 				xp_in_town_only: false,
+				hidden: 0,
+				found_by: BTreeSet::new(),
 			});
 	}
 
@@ -447,6 +547,15 @@ mod items_unit_test
 	use super::*;
 	use crate::mobile::*;
 
+	#[test]
+	fn indefinite_article_test()
+	{
+		assert_eq!(indefinite_article("apple"),"an");
+		assert_eq!(indefinite_article("Iron sword"),"an");
+		assert_eq!(indefinite_article("sword"),"a");
+		assert_eq!(indefinite_article(""),"a");
+	}
+
 	#[test]
 	fn add_rabbit_foot_test()
 	{
@@ -472,4 +581,18 @@ mod items_unit_test
 		let new_nut = mobile.fetch_item_by_name(&"nut".to_string());
 		assert!(new_nut.is_none());
 	}
+
+	#[test]
+	fn hidden_item_keeps_test()
+	{
+		let mut stone = Item::uncut_precious_stone();
+		stone.hidden = 50;
+		let lifetime = stone.lifetime;
+		stone.tick();
+		assert_eq!(stone.lifetime,lifetime);
+		let mut mobile = Mobile::new_character(&"Jim".to_string());
+		stone.got_item(&mut mobile,false);
+		stone.tick();
+		assert_eq!(stone.lifetime,lifetime-1);
+	}
 }
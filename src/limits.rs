@@ -0,0 +1,201 @@
+// Limits that stop one client from hogging the server: how many
+// sessions may be open at once, overall and from one address, and how
+// fast a session may send commands.
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::{Arc,Mutex};
+use std::time::{Duration,Instant};
+
+// How long to spend telling a client that it cannot come in
+pub const REFUSAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct SessionCounts
+{
+	total: usize,
+	by_address: BTreeMap<IpAddr,usize>
+}
+
+// Shared by the listeners. Cloning gives another handle to the same counts.
+#[derive(Clone)]
+pub struct ConnectionLimits
+{
+	max_sessions: usize,
+	max_sessions_per_address: usize,
+	counts: Arc<Mutex<SessionCounts>>
+}
+
+// Holds a place for a session. The place is given up when this is dropped.
+pub struct ConnectionGuard
+{
+	address: IpAddr,
+	counts: Arc<Mutex<SessionCounts>>
+}
+
+impl ConnectionLimits
+{
+	pub fn new(max_sessions: u16, max_sessions_per_address: u16) -> ConnectionLimits
+	{
+		return ConnectionLimits
+		{
+			max_sessions: max_sessions.into(),
+			max_sessions_per_address: max_sessions_per_address.into(),
+			counts: Arc::new(Mutex::new(SessionCounts { total: 0, by_address: BTreeMap::new() }))
+		};
+	}
+
+	// Let a connection in if there is room for it, otherwise say why not
+	pub fn admit(&self, address: IpAddr) -> Result<ConnectionGuard,String>
+	{
+		let mut counts = self.counts.lock().unwrap();
+		if counts.total >= self.max_sessions
+		{
+			return Err("The server is full. Please try again later.\n".to_string());
+		}
+		let from_address = counts.by_address.get(&address).copied().unwrap_or(0);
+		if from_address >= self.max_sessions_per_address
+		{
+			return Err("There are too many connections from your address.\n".to_string());
+		}
+		counts.total += 1;
+		counts.by_address.insert(address,from_address+1);
+		return Ok(ConnectionGuard { address: address, counts: self.counts.clone() });
+	}
+}
+
+impl Drop for ConnectionGuard
+{
+	fn drop(&mut self)
+	{
+		let mut counts = self.counts.lock().unwrap();
+		counts.total -= 1;
+		let from_address = counts.by_address.get(&self.address).copied().unwrap_or(1);
+		if from_address <= 1
+		{
+			counts.by_address.remove(&self.address);
+		}
+		else
+		{
+			counts.by_address.insert(self.address,from_address-1);
+		}
+	}
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RateCheck
+{
+	Allowed,
+	// Over the limit again in the same second
+	Dropped,
+	// Over the limit for the first time
+	Warn,
+	// Over the limit again after being warned
+	Disconnect
+}
+
+// Counts the commands that a session sends each second. Commands over
+// the limit are dropped. The first time brings a warning, and doing it
+// again in the next second that is busy brings a disconnect. A quiet
+// second forgives the warning.
+pub struct CommandRateLimiter
+{
+	per_second: u16,
+	window_start: Instant,
+	count: u16,
+	over_limit: bool,
+	warned: bool
+}
+
+impl CommandRateLimiter
+{
+	pub fn new(per_second: u16) -> CommandRateLimiter
+	{
+		return CommandRateLimiter
+		{
+			per_second: per_second,
+			window_start: Instant::now(),
+			count: 0,
+			over_limit: false,
+			warned: false
+		};
+	}
+
+	pub fn check(&mut self, now: Instant) -> RateCheck
+	{
+		if now.saturating_duration_since(self.window_start) >= Duration::from_secs(1)
+		{
+			if !self.over_limit
+			{
+				self.warned = false;
+			}
+			self.window_start = now;
+			self.count = 0;
+			self.over_limit = false;
+		}
+		self.count = self.count.saturating_add(1);
+		if self.count <= self.per_second
+		{
+			return RateCheck::Allowed;
+		}
+		if self.over_limit
+		{
+			return RateCheck::Dropped;
+		}
+		self.over_limit = true;
+		if self.warned
+		{
+			return RateCheck::Disconnect;
+		}
+		self.warned = true;
+		return RateCheck::Warn;
+	}
+}
+
+#[cfg(test)]
+mod limits_unit_test
+{
+	use super::*;
+
+	#[test]
+	fn connection_limits_test()
+	{
+		let limits = ConnectionLimits::new(3,2);
+		let home: IpAddr = "10.0.0.1".parse().unwrap();
+		let away: IpAddr = "10.0.0.2".parse().unwrap();
+		let first = limits.admit(home).unwrap();
+		let second = limits.admit(home).unwrap();
+		assert!(limits.admit(home).err().unwrap().contains("too many connections"));
+		let third = limits.admit(away).unwrap();
+		assert!(limits.admit(away).err().unwrap().contains("server is full"));
+		// Places are given back when the sessions end
+		drop(first);
+		let fourth = limits.admit(home).unwrap();
+		assert!(limits.admit(away).is_err());
+		drop(second);
+		drop(third);
+		drop(fourth);
+		let _fifth = limits.admit(away).unwrap();
+		let _sixth = limits.admit(away).unwrap();
+		let _seventh = limits.admit(home).unwrap();
+	}
+
+	#[test]
+	fn rate_limit_test()
+	{
+		let mut limiter = CommandRateLimiter::new(2);
+		let start = Instant::now();
+		let second = Duration::from_secs(1);
+		assert_eq!(limiter.check(start),RateCheck::Allowed);
+		assert_eq!(limiter.check(start),RateCheck::Allowed);
+		assert_eq!(limiter.check(start),RateCheck::Warn);
+		assert_eq!(limiter.check(start),RateCheck::Dropped);
+		// A quiet second forgives the warning
+		assert_eq!(limiter.check(start+second),RateCheck::Allowed);
+		assert_eq!(limiter.check(start+second*2),RateCheck::Allowed);
+		assert_eq!(limiter.check(start+second*2),RateCheck::Allowed);
+		assert_eq!(limiter.check(start+second*2),RateCheck::Warn);
+		// A busy one does not
+		assert_eq!(limiter.check(start+second*3),RateCheck::Allowed);
+		assert_eq!(limiter.check(start+second*3),RateCheck::Allowed);
+		assert_eq!(limiter.check(start+second*3),RateCheck::Disconnect);
+	}
+}
@@ -1,7 +1,7 @@
 // Basic types of locations for map generation
 use crate::object::Object;
 use crate::mobile::Mobile;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap,BTreeSet};
 use crate::items::*;
 use crate::message::*;
 
@@ -15,6 +15,67 @@ pub enum LocationTypeCode
 	Unexplored
 }
 
+impl LocationTypeCode
+{
+	pub fn name(&self) -> &str
+	{
+		match self
+		{
+			LocationTypeCode::Town => { return "Town"; },
+			LocationTypeCode::Forest => { return "Forest"; },
+			LocationTypeCode::DeepWoods => { return "Deep Woods"; },
+			LocationTypeCode::Hills => { return "Hills"; },
+			LocationTypeCode::Unexplored => { return "Unexplored"; }
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<LocationTypeCode>
+	{
+		match name
+		{
+			"Town" => { return Some(LocationTypeCode::Town); },
+			"Forest" => { return Some(LocationTypeCode::Forest); },
+			"Deep Woods" => { return Some(LocationTypeCode::DeepWoods); },
+			"Hills" => { return Some(LocationTypeCode::Hills); },
+			"Unexplored" => { return Some(LocationTypeCode::Unexplored); },
+			_ => { return None; }
+		}
+	}
+}
+
+// A way out of a location other than the eight directions, such as
+// a tunnel, taken with up or down
+pub struct Exit
+{
+	pub direction: String,
+	pub to: (i16,i16),
+	pub name: String,
+	// How hard it is to find, or 0 if it is in plain sight
+	pub hidden: i16,
+	// Those who have found it while it was hidden
+	pub found_by: BTreeSet<usize>
+}
+
+impl Exit
+{
+	pub fn new(direction: &str, to: (i16,i16), name: &str, hidden: i16) -> Exit
+	{
+		return Exit
+		{
+			direction: direction.to_string(),
+			to: to,
+			name: name.to_string(),
+			hidden: hidden,
+			found_by: BTreeSet::new()
+		};
+	}
+
+	pub fn is_visible_to(&self, uuid: usize) -> bool
+	{
+		return self.hidden == 0 || self.found_by.contains(&uuid);
+	}
+}
+
 // A location on the map
 pub struct Location
 {
@@ -23,7 +84,8 @@ pub struct Location
 	description: String,
 	pub location_type: LocationTypeCode,
 	mobiles: BTreeMap<usize,Box<Mobile> >,
-	items: Vec<Box<Item> >
+	items: Vec<Box<Item> >,
+	exits: Vec<Exit>
 }
 
 impl Object for Location
@@ -35,40 +97,165 @@ impl Object for Location
 
 	fn description(&self) -> String
 	{
-		let mut result = self.description.clone();
+		return self.description_seen_by(None);
+	}
+
+	fn get_name(&self) -> String { return self.description.clone(); }
+}
+
+impl Location
+{
+
+	// Describe the location as the viewer sees it. Hidden mobiles only
+	// show up if the viewer notices them, and hidden items and exits if
+	// the viewer has found them.
+	pub fn description_seen_by(&self, viewer: Option<usize>) -> String
+	{
+		let viewer_id = viewer;
+		let viewer = viewer.and_then(|viewer| self.mobiles.get(&viewer));
+		let mut result = "{C}".to_string()+&self.description+"{x}";
 		for (_,mobile) in self.mobiles.iter()
 		{
+			if !viewer.map(|viewer| mobile.is_visible_to(viewer.get_id())).unwrap_or(!mobile.hidden)
+			{
+				continue;
+			}
 			let description = mobile.description();
 			result += "\n";
 			result += &description;
 		}
 		for item in self.items.iter()
 		{
+			if !viewer_id.map(|viewer| item.is_visible_to(viewer)).unwrap_or(item.hidden == 0)
+			{
+				continue;
+			}
 			let description = item.description();
-			result += "\n";
+			result += "\n{y}";
 			result += &description;
+			result += "{x}";
+		}
+		for exit in self.exits.iter()
+		{
+			if !viewer_id.map(|viewer| exit.is_visible_to(viewer)).unwrap_or(exit.hidden == 0)
+			{
+				continue;
+			}
+			result += &("\n{C}There is ".to_string()+&exit.name+" leading "+&exit.direction+" here.{x}");
 		}
 		return result;
 	}
 
-	fn get_name(&self) -> String { return self.description.clone(); }
-}
+	pub fn exits(&self) -> &Vec<Exit>
+	{
+		return &self.exits;
+	}
 
-impl Location
-{
+	pub fn add_exit(&mut self, exit: Exit)
+	{
+		self.exits.push(exit);
+	}
+
+	// Where the exit in that direction goes, if the viewer knows of it
+	pub fn find_exit(&self, direction: &str, viewer: usize) -> Option<(i16,i16)>
+	{
+		for exit in self.exits.iter()
+		{
+			if exit.direction == direction && exit.is_visible_to(viewer)
+			{
+				return Some(exit.to);
+			}
+		}
+		return None;
+	}
+
+	// Look for hidden items and exits. Returns what the searcher found
+	// this time. Only the searcher gets to see them.
+	pub fn search(&mut self, searcher: &Mobile) -> Vec<String>
+	{
+		let id = searcher.get_id();
+		let mut found = Vec::new();
+		for item in self.items.iter_mut()
+		{
+			if !item.is_visible_to(id) && searcher.roll_perception() > item.hidden
+			{
+				item.found_by.insert(id);
+				found.push("a {y}".to_string()+&item.get_name()+"{x}");
+			}
+		}
+		for exit in self.exits.iter_mut()
+		{
+			if !exit.is_visible_to(id) && searcher.roll_perception() > exit.hidden
+			{
+				exit.found_by.insert(id);
+				found.push(exit.name.clone()+" leading "+&exit.direction);
+			}
+		}
+		return found;
+	}
+
+	// The mobile hides. Each of the others here gets one chance to see
+	// where it went.
+	pub fn hide_mobile(&mut self, uuid: usize)
+	{
+		let hider = match self.mobiles.get(&uuid)
+		{
+			Some(hider) => { hider },
+			None => { return; }
+		};
+		let spotted_by = self.noticed_by(hider).into_iter().collect();
+		let hider = self.mobiles.get_mut(&uuid).unwrap();
+		hider.hidden = true;
+		hider.spotted_by = spotted_by;
+	}
+
+	// A mobile has arrived. It gets one chance to spot each of those
+	// hiding here.
+	pub fn look_for_hidden(&mut self, uuid: usize)
+	{
+		let observer = match self.mobiles.get(&uuid)
+		{
+			Some(observer) => { observer },
+			None => { return; }
+		};
+		let spotted: Vec<usize> = self.mobiles.iter()
+			.filter(|(id,mobile)| **id != uuid && mobile.hidden && observer.notices(mobile))
+			.map(|(id,_)| *id)
+			.collect();
+		for id in spotted
+		{
+			self.mobiles.get_mut(&id).unwrap().spotted_by.insert(uuid);
+		}
+	}
+
+	// The mobiles here, other than the sneak, who notice the sneak
+	pub fn noticed_by(&self, sneak: &Mobile) -> Vec<usize>
+	{
+		let mut result = Vec::new();
+		for (id,mobile) in self.mobiles.iter()
+		{
+			if *id != sneak.get_id() && mobile.notices(sneak)
+			{
+				result.push(*id);
+			}
+		}
+		return result;
+	}
 
 	pub fn has_mobiles(&self) -> bool
 	{
 		return !self.mobiles.is_empty();
 	}
 
+	// Hidden items are left where they are
 	pub fn fetch_item_at_random(&mut self) -> Option<Box<Item> >
 	{
-		if self.items.len() == 0
+		let in_sight: Vec<usize> = (0..self.items.len()).filter(|i| self.items[*i].hidden == 0).collect();
+		if in_sight.len() == 0
 		{
 			return None;
 		}
-		let index = rand::random::<usize>() % self.items.len();
+		let index = in_sight[rand::random::<usize>() % in_sight.len()];
 		return Some(self.items.remove(index));
 	}
 
@@ -94,7 +281,8 @@ impl Location
 			description: description.clone(),
 			location_type: code.clone(),
 			mobiles: BTreeMap::new(),
-			items: Vec::new()
+			items: Vec::new(),
+			exits: Vec::new()
 		};
 		match result.location_type
 		{
@@ -112,12 +300,27 @@ impl Location
 		self.items.push(item);
 	}
 
+	// The items lying here
+	pub fn items(&self) -> &Vec<Box<Item> >
+	{
+		return &self.items;
+	}
+
+	pub fn clear_items(&mut self)
+	{
+		self.items.clear();
+	}
+
 	pub fn add_corpse(&mut self, mobile: &mut Box<Mobile>)
 	{
 		self.add_item(Item::corpse(mobile.name.clone()));
 		loop
 		{
-			let item = mobile.fetch_first_item();
+			let mut item = mobile.fetch_first_item();
+			if item.is_none()
+			{
+				item = mobile.fetch_equipped_item();
+			}
 			if item.is_some()
 			{
 				self.add_item(item.unwrap());
@@ -186,7 +389,8 @@ impl Location
 		}
 	}
 
-	pub fn fetch_mobile_by_name(&mut self, key: &String) -> Option<Box<Mobile> >
+	// Only mobiles that the viewer can see are found
+	pub fn fetch_mobile_by_name(&mut self, key: &String, viewer: usize) -> Option<Box<Mobile> >
 	{
 		let mut which = 0;
 		let mut idx = 0;
@@ -197,7 +401,7 @@ impl Location
 		{
 			let mut mobile_name = mobile.name.clone();
 			mobile_name.make_ascii_lowercase();	
-			if mobile_name.contains(&lower_case_key)
+			if mobile_name.contains(&lower_case_key) && mobile.is_visible_to(viewer)
 			{
 				if idx == which
 				{
@@ -209,7 +413,8 @@ impl Location
 		return None;
 	}
 
-	pub fn fetch_item_by_name(&mut self, key: &String) -> Option<Box<Item> >
+	// Only items that the viewer can see are found
+	pub fn fetch_item_by_name(&mut self, key: &String, viewer: usize) -> Option<Box<Item> >
 	{
 		let mut which = 0;
 		let mut idx = 0;
@@ -221,7 +426,7 @@ impl Location
 		{
 			let mut name = self.items[i].name.clone();
 			name.make_ascii_lowercase();
-    		if name.contains(&lower_case_key)
+    		if name.contains(&lower_case_key) && self.items[i].is_visible_to(viewer)
 			{
 				if idx == which
 				{
@@ -242,6 +447,75 @@ mod location_unit_test
 	use crate::items::*;
 	use crate::mobile::*;
 
+	#[test]
+	fn hidden_test()
+	{
+		let mut location = Location::new(0,0,LocationTypeCode::Town,"Town".to_string());
+		let rabbit = Mobile::rabbit();
+		let rabbit_id = rabbit.get_id();
+		let mut sharp = Mobile::new_character(&"Sharp".to_string());
+		sharp.perception = 100;
+		let sharp_id = sharp.get_id();
+		let mut dull = Mobile::new_character(&"Dull".to_string());
+		dull.perception = -100;
+		let dull_id = dull.get_id();
+		location.add_mobile(rabbit);
+		location.add_mobile(sharp);
+		location.add_mobile(dull);
+		location.hide_mobile(rabbit_id);
+		assert!(location.description_seen_by(Some(sharp_id)).contains("rabbit"));
+		assert!(!location.description_seen_by(Some(dull_id)).contains("rabbit"));
+		assert!(location.description_seen_by(Some(rabbit_id)).contains("rabbit"));
+		assert!(!location.description().contains("rabbit"));
+		// Nor can the rabbit be picked out by name
+		assert!(location.fetch_mobile_by_name(&"rabbit".to_string(),dull_id).is_none());
+		let rabbit = location.fetch_mobile_by_name(&"rabbit".to_string(),sharp_id).unwrap();
+		location.add_mobile(rabbit);
+		// Looking again is no second chance
+		let mut dull = location.fetch_mobile_by_guid(dull_id).unwrap();
+		dull.perception = 100;
+		location.add_mobile(dull);
+		assert!(!location.description_seen_by(Some(dull_id)).contains("rabbit"));
+		// Someone arriving gets a chance
+		let mut newcomer = Mobile::new_character(&"Newcomer".to_string());
+		newcomer.perception = 100;
+		let newcomer_id = newcomer.get_id();
+		location.add_mobile(newcomer);
+		location.look_for_hidden(newcomer_id);
+		assert!(location.description_seen_by(Some(newcomer_id)).contains("rabbit"));
+		let mut rabbit = location.fetch_mobile_by_guid(rabbit_id).unwrap();
+		assert!(rabbit.stop_hiding());
+		assert!(rabbit.spotted_by.is_empty());
+		rabbit.hidden = true;
+		assert!(!rabbit.is_visible_to(sharp_id));
+	}
+
+	#[test]
+	fn search_test()
+	{
+		let mut location = Location::new(0,0,LocationTypeCode::Hills,"Hills".to_string());
+		let mut stone = Item::uncut_precious_stone();
+		stone.hidden = 50;
+		location.add_item(stone);
+		location.add_exit(Exit::new("down",(0,0),"a narrow tunnel",50));
+		let mut sharp = Mobile::new_character(&"Sharp".to_string());
+		sharp.perception = 100;
+		let sharp_id = sharp.get_id();
+		let mut dull = Mobile::new_character(&"Dull".to_string());
+		dull.perception = -100;
+		let dull_id = dull.get_id();
+		assert!(location.search(&dull).is_empty());
+		assert!(location.find_exit("down",sharp_id).is_none());
+		assert_eq!(location.search(&sharp).len(),2);
+		assert!(location.search(&sharp).is_empty());
+		assert_eq!(location.find_exit("down",sharp_id),Some((0,0)));
+		assert!(location.find_exit("down",dull_id).is_none());
+		assert!(location.description_seen_by(Some(sharp_id)).contains("narrow tunnel"));
+		assert!(!location.description_seen_by(Some(dull_id)).contains("narrow tunnel"));
+		assert!(location.fetch_item_by_name(&"stone".to_string(),dull_id).is_none());
+		assert!(location.fetch_item_by_name(&"stone".to_string(),sharp_id).is_some());
+	}
+
 	#[test]
 	fn fetch_mobile_by_name()
 	{
@@ -252,13 +526,13 @@ mod location_unit_test
 		location.add_mobile(rabbit1);
 		location.add_mobile(rabbit2);
 		location.add_mobile(rabbit3);
-		let fetched = location.fetch_mobile_by_name(&"rabbit".to_string());
+		let fetched = location.fetch_mobile_by_name(&"rabbit".to_string(),0);
 		assert!(fetched.is_some());
 		location.add_mobile(fetched.unwrap());
-		let fetched = location.fetch_mobile_by_name(&"rabbit.0".to_string());
+		let fetched = location.fetch_mobile_by_name(&"rabbit.0".to_string(),0);
 		assert!(fetched.is_some());
 		location.add_mobile(fetched.unwrap());
-		let fetched = location.fetch_mobile_by_name(&"rabbit.1".to_string());
+		let fetched = location.fetch_mobile_by_name(&"rabbit.1".to_string(),0);
 		assert!(fetched.is_some());
 		location.add_mobile(fetched.unwrap());
 	}
@@ -295,9 +569,9 @@ mod location_unit_test
 		let mut location = Location::new(0,0,LocationTypeCode::Forest,"Forest".to_string());
 		let foot = Item::rabbit_foot();
 		location.add_item(foot);
-		let found_item = location.fetch_item_by_name(&"foot".to_string());
+		let found_item = location.fetch_item_by_name(&"foot".to_string(),0);
 		assert!(found_item.is_some());
-		let found_again = location.fetch_item_by_name(&"foot".to_string());
+		let found_again = location.fetch_item_by_name(&"foot".to_string(),0);
 		assert!(found_again.is_none());
 	}
 
@@ -307,7 +581,7 @@ mod location_unit_test
 		let mut location = Location::new(0,0,LocationTypeCode::Forest,"Forest".to_string());
 		let mut rabbit = Mobile::rabbit();
 		location.add_corpse(&mut rabbit);
-		let found_item = location.fetch_item_by_name(&"foot".to_string());
+		let found_item = location.fetch_item_by_name(&"foot".to_string(),0);
 		assert!(found_item.is_some());
 	}
 }
@@ -0,0 +1,230 @@
+use crate::session::*;
+use crate::telnet::Telnet;
+use crate::config::Config;
+use std::io::{ErrorKind,Read,Write};
+use std::sync::{Arc,mpsc::{self,Receiver,RecvTimeoutError,Sender}};
+use std::thread;
+use std::time::{Duration,Instant};
+
+// How long a scripted client waits for the text it expects
+const SCRIPT_TIMEOUT : Duration = Duration::from_secs(10);
+
+// An in-process connection that stands in for a socket
+pub struct LoopbackTransport
+{
+	from_client: Receiver<Vec<u8> >,
+	to_client: Sender<Vec<u8> >,
+	timeout: Option<Duration>,
+	// Bytes received but not yet read
+	pending: Vec<u8>
+}
+
+impl Transport for LoopbackTransport
+{
+	fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()>
+	{
+		self.timeout = timeout;
+		return Ok(());
+	}
+}
+
+impl Read for LoopbackTransport
+{
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize>
+	{
+		if self.pending.is_empty()
+		{
+			let data = match self.timeout
+			{
+				Some(timeout) => { self.from_client.recv_timeout(timeout) },
+				None => { self.from_client.recv().map_err(|_| RecvTimeoutError::Disconnected) }
+			};
+			match data
+			{
+				Ok(data) => { self.pending = data; },
+				Err(RecvTimeoutError::Timeout) => { return Err(std::io::Error::from(ErrorKind::WouldBlock)); },
+				// The client hung up
+				Err(RecvTimeoutError::Disconnected) => { return Ok(0); }
+			}
+		}
+		let n = std::cmp::min(buf.len(),self.pending.len());
+		buf[..n].copy_from_slice(&self.pending[..n]);
+		self.pending.drain(..n);
+		return Ok(n);
+	}
+}
+
+impl Write for LoopbackTransport
+{
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>
+	{
+		match self.to_client.send(buf.to_vec())
+		{
+			Ok(_) => { return Ok(buf.len()); },
+			Err(_) => { return Err(std::io::Error::from(ErrorKind::BrokenPipe)); }
+		}
+	}
+
+	fn flush(&mut self) -> std::io::Result<()>
+	{
+		return Ok(());
+	}
+}
+
+// A client that follows a script. It connects to the game through a
+// loopback transport so that tests can play without a socket.
+pub struct ScriptedClient
+{
+	to_server: Sender<Vec<u8> >,
+	from_server: Receiver<Vec<u8> >,
+	// Strips the telnet negotiation from what the server sends
+	telnet: Telnet,
+	// Text received but not yet matched by wait_for
+	received: String
+}
+
+impl ScriptedClient
+{
+	// Start a session for this client in its own thread
+	pub fn connect(game: Sender<ClientMessage>, config: Config) -> ScriptedClient
+	{
+		let (to_server, from_client) = mpsc::channel();
+		let (to_client, from_server) = mpsc::channel();
+		let transport = LoopbackTransport
+		{
+			from_client: from_client,
+			to_client: to_client,
+			timeout: None,
+			pending: Vec::new()
+		};
+		thread::spawn(move ||
+			{
+				handle_connection(Box::new(transport),game,Arc::new(config));
+			}
+		);
+		return ScriptedClient
+		{
+			to_server: to_server,
+			from_server: from_server,
+			telnet: Telnet::new(),
+			received: String::new()
+		};
+	}
+
+	pub fn send(&mut self, line: &str)
+	{
+		let _ = self.to_server.send((line.to_string()+"\r\n").into_bytes());
+	}
+
+	// Read until the text appears. Returns everything up to and including the text.
+	pub fn wait_for(&mut self, text: &str) -> String
+	{
+		return self.wait_for_any(&[text]);
+	}
+
+	// Read until any of the texts appears, and return up to the end of
+	// the first one that does
+	pub fn wait_for_any(&mut self, texts: &[&str]) -> String
+	{
+		let deadline = Instant::now()+SCRIPT_TIMEOUT;
+		loop
+		{
+			let found = texts.iter().filter_map(|text| self.received.find(text).map(|found| found+text.len())).min();
+			if let Some(end) = found
+			{
+				let rest = self.received.split_off(end);
+				return std::mem::replace(&mut self.received,rest);
+			}
+			let now = Instant::now();
+			if now >= deadline
+			{
+				panic!("expected {:?} but got \"{}\"",texts,self.received);
+			}
+			match self.from_server.recv_timeout(deadline-now)
+			{
+				Ok(data) =>
+					{
+						let data = self.telnet.receive(&data);
+						self.received += &String::from_utf8_lossy(&data);
+					},
+				Err(RecvTimeoutError::Timeout) => { () },
+				Err(RecvTimeoutError::Disconnected) =>
+					{
+						panic!("disconnected while expecting {:?}, got \"{}\"",texts,self.received);
+					}
+			}
+		}
+	}
+
+	// Wait until the server closes the connection. Returns what was received.
+	pub fn wait_for_close(&mut self) -> String
+	{
+		let deadline = Instant::now()+SCRIPT_TIMEOUT;
+		loop
+		{
+			let now = Instant::now();
+			if now >= deadline
+			{
+				panic!("expected the connection to close, got \"{}\"",self.received);
+			}
+			match self.from_server.recv_timeout(deadline-now)
+			{
+				Ok(data) =>
+					{
+						let data = self.telnet.receive(&data);
+						self.received += &String::from_utf8_lossy(&data);
+					},
+				Err(RecvTimeoutError::Timeout) => { () },
+				Err(RecvTimeoutError::Disconnected) => { return std::mem::take(&mut self.received); }
+			}
+		}
+	}
+
+	// Create a new character and wait for the first prompt
+	pub fn create_character(&mut self, name: &str, password: &str)
+	{
+		self.wait_for("What is your name? ");
+		self.send(name);
+		self.wait_for("Keep this character (y/n)? ");
+		self.send("y");
+		self.wait_for("Choose a password: ");
+		self.send(password);
+		self.wait_for("Repeat the password: ");
+		self.send(password);
+		self.wait_for(">> ");
+	}
+}
+
+#[cfg(test)]
+mod loopback_unit_test
+{
+	use super::*;
+
+	#[test]
+	fn transport_test()
+	{
+		let (to_server, from_client) = mpsc::channel();
+		let (to_client, from_server) = mpsc::channel();
+		let mut transport = LoopbackTransport
+		{
+			from_client: from_client,
+			to_client: to_client,
+			timeout: Some(Duration::from_millis(10)),
+			pending: Vec::new()
+		};
+		let mut buf = vec![0;4];
+		let result = transport.read(&mut buf);
+		assert_eq!(result.unwrap_err().kind(),ErrorKind::WouldBlock);
+		to_server.send(b"hello".to_vec()).unwrap();
+		assert_eq!(transport.read(&mut buf).unwrap(),4);
+		assert_eq!(&buf,b"hell");
+		assert_eq!(transport.read(&mut buf).unwrap(),1);
+		assert_eq!(buf[0],b'o');
+		transport.write_all(b"hi").unwrap();
+		assert_eq!(from_server.recv().unwrap(),b"hi".to_vec());
+		drop(to_server);
+		assert_eq!(transport.read(&mut buf).unwrap(),0);
+		drop(from_server);
+		assert!(transport.write_all(b"hi").is_err());
+	}
+}
@@ -1,7 +1,6 @@
 use std::{
-	collections::LinkedList, fs, io::{prelude::*,ErrorKind}, net::{TcpListener,TcpStream}, sync::{Arc,Mutex}, thread, time::{Duration,SystemTime}
+	collections::{BTreeMap,VecDeque}, net::{TcpListener,TcpStream}, sync::{Arc,atomic::{AtomicBool,Ordering},mpsc::{self,Receiver,RecvTimeoutError,Sender}}, thread, time::{Duration,Instant,SystemTime}
 };
-use location::{LocationTypeCode};
 
 mod items;
 mod world;
@@ -13,642 +12,961 @@ mod events;
 mod message;
 mod dice;
 mod uid;
+mod telnet;
+mod session;
+mod account;
+mod websocket;
+mod config;
+mod gmcp;
+mod color;
+mod prompt;
+mod limits;
+mod commands;
+mod trade;
+#[cfg(test)]
+mod loopback;
 use crate::world::*;
 use crate::object::*;
 use crate::events::*;
-use crate::mobile::*;
+use crate::session::*;
+use crate::websocket::*;
+use crate::config::*;
+use crate::limits::*;
+use crate::commands::*;
 
-// Tick in milliseconds
-const TICK : u16 = 250;
-// Sub ticks for interval between checking
-// messages, getting input, and so forth
-const SUB_TICK : u16 = 50;
+// Most commands that can wait in a player's queue
+const MAX_QUEUED_COMMANDS : usize = 20;
 
-// If we are removed, then exit. Otherwise return our position
-fn check_if_removed(uuid: usize, world: &mut WorldState, stream: &mut TcpStream, last_msg_read_time: SystemTime) -> bool
+// A player that is connected to the game
+struct Player
 {
-	if world.mobile_active(uuid)
-	{
-		return false;
-	}
-	else
-	{
-		let last_msgs = world.message_list.read_targetted(uuid,last_msg_read_time);
-		stream.write_all(b"\n").unwrap();
-		stream.write_all(last_msgs.as_bytes()).unwrap();
-		stream.flush().unwrap();
-		stream.write_all(b"Goodbye!\n").unwrap();
-		stream.flush().unwrap();
-		return true;
-	}
+	uuid: usize,
+	// Where to send the output for this player
+	output: Sender<ServerMessage>,
+	// Output waiting to be sent
+	message_for_user: String,
+	// Did we process a command since the last output was sent?
+	has_processed_command: bool,
+	last_message_list_read_time: SystemTime,
+	// Render colour markup as ANSI codes?
+	color: bool,
+	// The last GMCP message sent for each package
+	gmcp: BTreeMap<String,String>,
+	// Commands waiting for their turn
+	commands: VecDeque<String>,
+	// Commands run since the last tick
+	commands_this_tick: u16,
+	// When the connection was lost. The character stays in the
+	// world for a while in case the player comes back.
+	link_dead_since: Option<Instant>,
+	// The prompt last sent to the session
+	prompt: String,
+	// When the player last typed something
+	last_input: Instant
 }
 
-fn get_item(uuid: usize, world: &mut WorldState, target: &String) -> String
+impl Player
 {
-	let mut result = "Got it!".to_string();
-	let position = world.find_mobile_location(uuid).unwrap();
-	let mut mobile = world.fetch_mobile(uuid).unwrap();
-	let item = world.fetch_item_by_name(position.0,position.1,target);
-	if item.is_some()
-	{
-		let item = item.unwrap();
-		if mobile.has_room_for_item(&item)
-		{
-			mobile.add_item(item,true);
-		}
-		else
+	// Send a GMCP message if it differs from the last one for its package
+	fn send_gmcp(&mut self, message: String)
+	{
+		let package = message.split(' ').next().unwrap_or("").to_string();
+		if self.gmcp.get(&package) == Some(&message)
 		{
-			result = "You don't have space for that!".to_string();
-			world.add_item(position.0,position.1,item);
+			return;
 		}
+		let _ = self.output.send(ServerMessage::Gmcp(message.clone()));
+		self.gmcp.insert(package,message);
 	}
-	else
-	{
-		result = "Get what?".to_string();
-	}
-	world.add_mobile(mobile,position.0,position.1);
-	return result;
 }
 
-fn make_item(uuid: usize, event_q: &mut EventList, target: &String) -> String
+fn handle_client_message(message: ClientMessage, world: &mut WorldState, players: &mut BTreeMap<usize,Player>)
 {
-	match target.as_ref()
+	match message
 	{
-		"rawhide" =>
+		ClientMessage::Login { session, character, output } =>
 			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::Rawhide}));
-				return "You begin making rawhide".to_string();
+				let uuid = character.get_id();
+				let color = character.color;
+				let name = character.name.clone();
+				if !world.mobile_exists(uuid)
+				{
+					world.add_mobile(character,0,0);
+					world.look_for_hidden(0,0,uuid);
+				}
+				// Is someone already playing this character?
+				let mut message_for_user = String::new();
+				let old_sessions: Vec<usize> = players.iter().filter(|(_,p)| p.uuid == uuid).map(|(s,_)| *s).collect();
+				for old_session in old_sessions
+				{
+					let old_player = players.remove(&old_session).unwrap();
+					if old_player.link_dead_since.is_some()
+					{
+						let position = world.find_mobile_location(uuid).unwrap();
+						world.message_list.broadcast(name.clone()+" has reconnected.",position.0,position.1);
+					}
+					else
+					{
+						let _ = old_player.output.send(ServerMessage::Output("\nSomeone else has logged in as you.\n".to_string()));
+						let _ = old_player.output.send(ServerMessage::Close);
+					}
+					message_for_user = "Reconnected.\n".to_string();
+				}
+				let player = Player
+				{
+					uuid: uuid,
+					output: output,
+					message_for_user: message_for_user,
+					has_processed_command: false,
+					last_message_list_read_time: SystemTime::now(),
+					color: color,
+					gmcp: BTreeMap::new(),
+					commands: VecDeque::new(),
+					commands_this_tick: 0,
+					link_dead_since: None,
+					prompt: String::new(),
+					last_input: Instant::now()
+				};
+				players.insert(session,player);
 			},
-		"leatherarmor" =>
+		ClientMessage::Command { session, line } =>
 			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::LeatherArmor}));
-				return "You begin making leather armor".to_string();
+				let player = players.get_mut(&session);
+				if player.is_none()
+				{
+					return;
+				}
+				let player = player.unwrap();
+				player.last_input = Instant::now();
+				// Make sure we are alive before queueing a command
+				if !world.mobile_active(player.uuid)
+				{
+					return;
+				}
+				// Several commands can be given at once, separated by ';'
+				for command in line.split(';').map(|c| c.trim()).filter(|c| !c.is_empty())
+				{
+					if player.commands.len() >= MAX_QUEUED_COMMANDS
+					{
+						player.message_for_user += "You have too many commands waiting.\n";
+						break;
+					}
+					player.commands.push_back(command.to_string());
+				}
+				// An empty line gets a new prompt
+				if player.commands.is_empty()
+				{
+					player.has_processed_command = true;
+				}
 			},
-		"hidearmor" =>
+		ClientMessage::Disconnect { session } =>
 			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::HideArmor }));
-				return "You begin making hide armor".to_string();
-			}
-		"pointedstick" =>
-			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::PointedStick }));
-				return "You begin sharpening a stick".to_string();
-			}
-		"knife" =>
-			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::StoneKnife }));
-				return "You begin knapping a stone".to_string();
-			}
-		"sword" =>
-			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::Sword }));
-				return "You begin forging a sword".to_string();
-			}
-		"axe" =>
-			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::Axe }));
-				return "You begin forging an axe".to_string();
+				let player = players.get_mut(&session);
+				if player.is_none()
+				{
+					return;
+				}
+				let player = player.unwrap();
+				player.link_dead_since = Some(Instant::now());
+				player.commands.clear();
+				let uuid = player.uuid;
+				if world.mobile_active(uuid)
+				{
+					let position = world.find_mobile_location(uuid).unwrap();
+					let mobile = world.fetch_mobile(uuid).unwrap();
+					world.message_list.broadcast(mobile.name.clone()+" has lost their link.",position.0,position.1);
+					world.add_mobile(mobile,position.0,position.1);
+				}
 			}
-		"pick" =>
+	}
+}
+
+// Run the commands that players have queued, as many as each player
+// is allowed in this tick
+fn run_commands(world: &mut WorldState, event_q: &mut EventList, players: &mut BTreeMap<usize,Player>, config: &Config, commands: &CommandRegistry)
+{
+	// Players whose link is dead are not counted as online
+	let online: Vec<OnlinePlayer> = players.values()
+		.filter(|player| player.link_dead_since.is_none())
+		.map(|player| OnlinePlayer { uuid: player.uuid, idle: player.last_input.elapsed() })
+		.collect();
+	for (_,player) in players.iter_mut()
+	{
+		while player.commands_this_tick < config.commands_per_tick && world.mobile_active(player.uuid)
+		{
+			let line = match player.commands.pop_front()
 			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::Pick }));
-				return "You begin forging a pick".to_string();
-			}
-		"chainmail" =>
+				Some(line) => { line },
+				None => { break; }
+			};
+			let mut context = CommandContext { uuid: player.uuid, world: world, event_q: event_q, config: config, commands: commands, online: &online };
+			let result = commands.dispatch(&line,&mut context);
+			player.message_for_user += &result;
+			// Keep the results of queued commands apart
+			if !result.is_empty() && !result.ends_with('\n')
 			{
-				event_q.insert(Box::new(MakeItemEvent { maker: uuid, item: items::ItemTypeCode::ChainArmor }));
-				return "You begin make a shirt of chain".to_string();
+				player.message_for_user.push('\n');
 			}
-		_ => { return "What is ".to_string()+target+&"?".to_string(); }
+			player.has_processed_command = true;
+			player.commands_this_tick += 1;
+		}
 	}
 }
 
-fn practice(uuid: usize, world: &mut WorldState, skill: &String) -> String
+// Stash the characters of players whose link has been dead for too long
+fn stash_link_dead(world: &mut WorldState, players: &mut BTreeMap<usize,Player>, config: &Config)
 {
-	let mut found_skill = true;
-	let mut success = false;
-	let position = world.find_mobile_location(uuid).unwrap();
-	let mut mobile = world.fetch_mobile(uuid).unwrap();
-	if skill == "combat"
-	{
-		success = mobile.practice_combat();
-	}
-	else if skill == "steal"
-	{
-		success = mobile.practice_steal();
-	}
-	else if skill == "stealth"
-	{
-		success = mobile.practice_stealth();
-	}
-	else if skill == "perception"
-	{
-		success = mobile.practice_perception();
-	}
-	else if skill == "knowledge"
-	{
-		success = mobile.practice_knowledge();
-	}
-	else if skill == "leatherwork"
-	{
-		success = mobile.practice_leatherwork();
-	}
-	else if skill == "woodcraft"
-	{
-		success = mobile.practice_woodcraft();
-	}
-	else if skill == "metalwork"
-	{
-		success = mobile.practice_metalwork();
-	}
-	else
-	{
-		found_skill = false; 
-	}
-	world.add_mobile(mobile,position.0,position.1);
-	if !found_skill
+	let grace_period = Duration::from_secs(config.link_dead_seconds.into());
+	let mut expired = Vec::new();
+	for (session,player) in players.iter()
 	{
-		return "Practice what?".to_string();
+		match player.link_dead_since
+		{
+			Some(since) if since.elapsed() >= grace_period => { expired.push(*session); },
+			_ => { () }
+		}
 	}
-	else if !success
+	for session in expired
 	{
-		return "Not enough xp!".to_string();
+		let player = players.remove(&session).unwrap();
+		if !world.mobile_active(player.uuid)
+		{
+			continue;
+		}
+		let position = world.find_mobile_location(player.uuid).unwrap();
+		let name = world.get_mobile_name(player.uuid).unwrap();
+		world.message_list.broadcast(name+" fades away.",position.0,position.1);
+		world.stash_mobile(player.uuid);
 	}
-	return "You have improved at ".to_string()+&skill+"!";
 }
 
-fn drop_item(uuid: usize, world: &mut WorldState, target: &String) -> String
+// Have the sessions ask for the passwords that players want to change
+fn ask_for_passwords(world: &mut WorldState, players: &mut BTreeMap<usize,Player>)
 {
-	let mut result = "Dropped it!".to_string();
-	let position = world.find_mobile_location(uuid).unwrap();
-	let mut mobile = world.fetch_mobile(uuid).unwrap();
-	let item = mobile.fetch_item_by_name(&target);
-	if item.is_some()
-	{
-		let item = item.unwrap();
-		if item.xp_in_town_only && world.get_location_type(position.0, position.1) == LocationTypeCode::Town
-		{
-			result = "A collector eagerly accepts the ".to_string()+&item.name+"!";
-			mobile.xp += item.xp_value;
-		}
-		else
+	for request in world.password_requests.drain(..)
+	{
+		for (_,player) in players.iter()
 		{
-			world.add_item(position.0,position.1,item);
+			if player.uuid == request.uuid && player.link_dead_since.is_none()
+			{
+				let _ = player.output.send(ServerMessage::ChangePassword { name: request.name.clone(), check_old: request.check_old });
+			}
 		}
 	}
-	else
-	{
-		result = "Drop what?".to_string();
-	}
-	world.add_mobile(mobile,position.0,position.1);
-	return result;
 }
 
-fn eat_item(uuid: usize, world: &mut WorldState, target: &String) -> String
-{
-	let position = world.find_mobile_location(uuid).unwrap();
-	let mut mobile = world.fetch_mobile(uuid).unwrap();
-	let result = mobile.eat_item_by_name(target);
-	world.add_mobile(mobile,position.0,position.1);
-	return result;
-}
+// Seconds before a shutdown at which players are warned again
+const SHUTDOWN_WARNINGS : [u64; 8] = [300,60,30,10,5,3,2,1];
 
-fn kill(uuid: usize, world: &mut WorldState, event_q: &mut EventList, target: &String)
+// Tell everyone how long they have before the server stops. The
+// last warning given is kept so that each one is only given once.
+fn warn_of_shutdown(world: &mut WorldState, last_warning: &mut Option<u64>)
 {
-	let position = world.find_mobile_location(uuid).unwrap();
-	let defender = world.get_mobile_id_by_name(position.0,position.1,&target);
-	match defender
+	let shutdown_at = match world.shutdown_at
+	{
+		Some(shutdown_at) => { shutdown_at },
+		None => { *last_warning = None; return; }
+	};
+	let remaining = shutdown_at.saturating_duration_since(Instant::now());
+	// Round up so that the count never reaches zero before the end
+	let seconds = remaining.as_secs()+if remaining.subsec_nanos() > 0 { 1 } else { 0 };
+	if seconds == 0
 	{
-		Some(defender) => { event_q.insert(Box::new(CombatEvent { attacker: uuid, defender: defender, first_round: true })); },
-		_ => { return; }
+		return;
 	}
-}
-
-fn steal(uuid: usize, world: &mut WorldState, event_q: &mut EventList, target: &String)
-{
-	let position = world.find_mobile_location(uuid).unwrap();
-	let mark = world.get_mobile_id_by_name(position.0,position.1,&target);
-	match mark
+	let warn = match *last_warning
+	{
+		// A new countdown, or one that was put back
+		None => { true },
+		Some(last) if seconds > last => { true },
+		Some(last) => { seconds < last && SHUTDOWN_WARNINGS.contains(&seconds) }
+	};
+	if warn
 	{
-		Some(mark) => { event_q.insert(Box::new(StealEvent { thief: uuid, mark: mark })); },
-		_ => { return; }
+		let unit = if seconds == 1 { " second." } else { " seconds." };
+		world.message_list.post_for_everyone("{R}The server will shut down in ".to_string()+&seconds.to_string()+unit+"{x}");
+		*last_warning = Some(seconds);
 	}
 }
 
-fn goto(uuid: usize, dx: i16, dy: i16, event_q: &mut EventList)
+// Save the characters in play, so that little is lost if the
+// server stops without warning
+fn save_players(world: &mut WorldState, players: &BTreeMap<usize,Player>)
 {
-	let move_event = Box::new(MoveMobileEvent
+	for (_,player) in players.iter()
+	{
+		if world.mobile_active(player.uuid)
 		{
-			uuid: uuid,
-			dx: dx,
-			dy: dy
-		});
-	event_q.insert(move_event);
+			let position = world.find_mobile_location(player.uuid).unwrap();
+			let mobile = world.fetch_mobile(player.uuid).unwrap();
+			mobile.save_to_file(&world.save_directory);
+			world.add_mobile(mobile,position.0,position.1);
+		}
+	}
 }
 
-fn look_at(uuid: usize, world: &mut WorldState, target: &String) -> String
+// Save every character and the world, and send everyone away
+fn shut_down(world: &mut WorldState, players: &mut BTreeMap<usize,Player>)
 {
-	let position = world.find_mobile_location(uuid).unwrap();
-	let mobile = world.fetch_mobile_by_name(position.0,position.1,&target);
-	if mobile.is_some()
+	for (_,player) in players.iter()
 	{
-		let mobile = mobile.unwrap();
-		let description = mobile.description()+"\nCarrying:\n"+&mobile.list_inventory();
-		world.add_mobile(mobile,position.0,position.1);
-		return description;
+		if world.mobile_active(player.uuid)
+		{
+			world.stash_mobile(player.uuid);
+		}
+		if player.link_dead_since.is_none()
+		{
+			let goodbye = "\n{R}The server is shutting down.{x} Goodbye!\n";
+			let _ = player.output.send(ServerMessage::Output(color::render(goodbye,player.color)));
+			let _ = player.output.send(ServerMessage::Close);
+		}
 	}
-	let item = world.fetch_item_by_name(position.0,position.1,&target);
-	if item.is_some()
+	players.clear();
+	if !world.save_to_file()
 	{
-		let item = item.unwrap();
-		let description = item.description();
-		world.add_item(position.0,position.1,item);
-		return description;
+		eprintln!("Could not save the world in {}",world.save_directory);
 	}
-	return "Look at what?".to_string();	
-}
-
-fn look(uuid: usize, world: &mut WorldState) -> String
-{
-	let position = world.find_mobile_location(uuid).unwrap();
-	return world.get_location_description(position.0,position.1);
-}
-
-fn show_inventory(uuid: usize, world: &mut WorldState) -> String
-{
-	let position = world.find_mobile_location(uuid).unwrap();
-	let mobile = world.fetch_mobile(uuid).unwrap();
-	let inventory = mobile.list_inventory();
-	world.add_mobile(mobile,position.0,position.1);
-	return ("You have:\n".to_owned()+&inventory).to_string();
-}
-
-fn show_stats_of(uuid: usize, world: &mut WorldState, target: &String) -> String
-{
-	let position = world.find_mobile_location(uuid).unwrap();
-	let scholar = world.fetch_mobile(uuid).unwrap();
-	// Is this a mobile at our location?
-	let mobile = world.fetch_mobile_by_name(position.0,position.1,&target);
-	if mobile.is_some()
-	{
-		let mobile = mobile.unwrap();
-		let success = scholar.roll_knowledge() > mobile.frequency;
-		let description =
-			if !success { "Perhaps you should study harder?".to_string() } 
-			else { mobile.complete_description() };
-		world.add_mobile(mobile,position.0,position.1);
-		world.add_mobile(scholar,position.0,position.1);
-		return description;
-	}
-	// Is this an item at our location?
-	let item = world.fetch_item_by_name(position.0,position.1,&target);
-	if item.is_some()
-	{
-		let item = item.unwrap();
-		let success = scholar.roll_knowledge() > item.frequency;
-		let description =
-			if !success { "Perhaps you should study harder?".to_string() } 
-			else { item.complete_description() };
-		world.add_item(position.0,position.1,item);
-		world.add_mobile(scholar,position.0,position.1);
-		return description;
-	}
-	// Nope. Nothing to stat.
-	world.add_mobile(scholar,position.0,position.1);
-	return "Stat what?".to_string();	
 }
 
-fn show_stats(uuid: usize, world: &mut WorldState) -> String
+// Send the results of commands and any messages in the global
+// message list to the players
+fn deliver_messages(world: &mut WorldState, players: &mut BTreeMap<usize,Player>)
 {
-	let position = world.find_mobile_location(uuid).unwrap();
-	let mobile = world.fetch_mobile(uuid).unwrap();
-	let result = mobile.complete_description();
-	world.add_mobile(mobile,position.0,position.1);
-	return result;
-}
-
-fn load_character(world_obj: Arc<Mutex<WorldState> >, mut stream: &TcpStream) -> Option<usize>
-{
-	let mut result = None;
-	stream.write_all(b"Welcome!\n").unwrap();
-	stream.flush().unwrap();
-	stream.write_all(b"What is your name? ").unwrap();
-	stream.flush().unwrap();
-	let mut buf = vec![0;128];
-	let n = match stream.read(&mut buf)
-	{
-		Err(_e) => { return None; },
-		Ok(m) => { m }
-	};
-	if n == 0 { return None; }
-	buf.truncate(n);
-	let line = String::from_utf8_lossy(&buf);
-	let name = line.trim();
-	loop
+	let mut removed = Vec::new();
+	for (session,player) in players.iter_mut()
 	{
-		let mut character = Mobile::new_character(&name.to_string());
+		let uuid = player.uuid;
+		// Nobody is listening to a dead link
+		if player.link_dead_since.is_some()
 		{
-			let mut world = world_obj.lock().unwrap();
-			if character.load_from_file()
+			if !world.mobile_active(uuid)
 			{
-				let uuid = character.get_id();
-				if !world.mobile_exists(uuid)
-				{
-					world.add_mobile(character,0,0);
-				}
-				result = Some(uuid);
-				break;
+				removed.push(*session);
 			}
+			continue;
 		}
-		stream.write_all(character.complete_description().as_bytes()).unwrap();
-		stream.write_all(b"Keep this character (y/n)? ").unwrap();
-		stream.flush().unwrap();
-		let mut buf = vec![0;128];
-		let n = match stream.read(&mut buf)
+		// If we are removed, then say goodbye and close the session
+		if !world.mobile_active(uuid)
 		{
-			Err(_e) => { break; },
-			Ok(m) => { m }
-		};
-		if n == 0 { break; }
-		buf.truncate(n);
-		let line = String::from_utf8_lossy(&buf);
-		let clean_line = line.trim();
-		if clean_line.contains(&"y")
+			let last_msgs = world.message_list.read_targetted(uuid,player.last_message_list_read_time);
+			let goodbye = "\n".to_string()+&last_msgs+"Goodbye!\n";
+			let _ = player.output.send(ServerMessage::Output(color::render(&goodbye,player.color)));
+			let _ = player.output.send(ServerMessage::Close);
+			removed.push(*session);
+			continue;
+		}
+		let position = world.find_mobile_location(uuid).unwrap();
+		let mut mobile = world.fetch_mobile(uuid).unwrap();
+		mobile.visited.insert(position);
+		player.color = mobile.color;
+		player.send_gmcp(gmcp::char_vitals(&mobile));
+		player.send_gmcp(gmcp::char_stats(&mobile));
+		player.send_gmcp(gmcp::char_items(&mobile));
+		let location_type = world.get_location_type(position.0,position.1);
+		player.send_gmcp(gmcp::room_info(position.0,position.1,location_type));
+		// The session shows the new prompt after the output that follows
+		let prompt = color::render(&prompt::render(&mobile.prompt,&mobile,position.0,position.1,location_type),player.color);
+		if prompt != player.prompt
 		{
-			let id = character.get_id();
-			let mut world = world_obj.lock().unwrap();
-			world.add_mobile(character,0,0);
-			result = Some(id);
-			break;
+			let _ = player.output.send(ServerMessage::Prompt(prompt.clone()));
+			player.prompt = prompt;
+		}
+		world.add_mobile(mobile,position.0,position.1);
+		player.message_for_user += &world.message_list.read(position.0,position.1,uuid,player.last_message_list_read_time);
+		player.last_message_list_read_time = SystemTime::now();
+		if !player.message_for_user.is_empty() || player.has_processed_command
+		{
+			let message = std::mem::take(&mut player.message_for_user);
+			let _ = player.output.send(ServerMessage::Output(color::render(&message,player.color)));
+			player.has_processed_command = false;
 		}
 	}
-	return result;
-}
-
-fn process_command(command: &mut LinkedList<String>, uuid: usize, world: &mut WorldState, event_q: &mut EventList) -> String
-{
-	if command.is_empty()
-	{
-		return "What?".to_string();
-	}
-	let action = command.pop_front();
-	match action.unwrap().as_ref()
+	for session in removed
 	{
-		"e" => { goto(uuid,1,0,event_q); return String::new(); }
-		"w" => { goto(uuid,-1,0,event_q); return String::new(); }
-		"n" => { goto(uuid,0,1,event_q); return String::new(); } 
-		"s" => { goto(uuid,0,-1,event_q); return String::new(); }
-		"help" =>
-			{
-				return fs::read_to_string("help.txt").expect("could not read help.txt");
-			}
-		"eat" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { return eat_item(uuid,world,target); },
-					None => { return "Eat what?".to_string(); }
-				}
-			},
-		"look" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { return look_at(uuid,world,target); },
-					None => { return look(uuid,world); }
-				}
-			},
-		"kill" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { kill(uuid,world,event_q,&target); return String::new(); },
-					None => { return "Kill what?".to_string(); }
-				}
-			},
-		"steal" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { steal(uuid,world,event_q,target); return String::new(); },
-					None => { return "Steal from whom?".to_string(); }
-				}
-			},
-		"get" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { return get_item(uuid,world,target); },
-					None => { return "Get what?".to_string(); }
-				}
-			},
-		"drop" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { return drop_item(uuid,world,target); },
-					None => { return "Drop what?".to_string(); }
-				}
-			},
-		"prac" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { return practice(uuid,world,target); },
-					None => { return "Practice what?".to_string(); }
-				}
-			},
-		"quit" =>
-			{
-				world.stash_mobile(uuid);
-				return "Goodbye!".to_string();
-			},
-		"stat" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { return show_stats_of(uuid,world,target); },
-					None => { return show_stats(uuid,world); }
-				}
-			},
-		"i" => { return show_inventory(uuid,world); },
-		"make" =>
-			{
-				let target = command.pop_front();
-				match target.as_ref()
-				{
-					Some(target) => { return make_item(uuid,event_q,target); },
-					None => { return "Make what?".to_string(); }
-				}
-			},
-		_ =>
-			{
-				return "What?".to_string();
-			}
+		players.remove(&session);
 	}
 }
 
-// Get input from the user and dispatch commands
-fn handle_connection(mut stream: TcpStream, world_obj: Arc<Mutex<WorldState> >, event_q_obj: Arc<Mutex<EventList> >)
+// The simulation. This is the only thread that touches the world and
+// the event queue. The sessions send it commands over a channel and it
+// sends their output back.
+// The stop flag is set when the process is asked to stop. The first
+// time starts the countdown and the second stops the server at once.
+fn game_loop(mut world: WorldState, inbox: Receiver<ClientMessage>, config: Arc<Config>, stop: Arc<AtomicBool>)
 {
-	let mut command : LinkedList<String> = LinkedList::new();
-	let mut print_prompt = true;
-	let mut _now = SystemTime::now();
-	let mut last_message_list_read_time = SystemTime::now();
-	let mut message_for_user = String::new();
-	let uuid = 
-		match load_character(world_obj.clone(),&stream)
-		{
-			Some(uuid) => { uuid },
-			None => { return; }
-		};
-	let mut input: Vec<u8> = vec![];
-	let _ = stream.set_read_timeout(Some(Duration::from_millis(SUB_TICK.into())));
-	let mut last_output_char = '\n';
+	let tick_duration = Duration::from_millis(config.tick.into());
+	let sub_tick_duration = Duration::from_millis(config.sub_tick.into());
+	let save_duration = Duration::from_secs(config.save_seconds.into());
+	world.save_directory = config.save_directory.clone();
+	let mut event_q = EventList::new();
+	let commands = CommandRegistry::standard();
+	let mut players: BTreeMap<usize,Player> = BTreeMap::new();
+	// Default events
+	let wandering_monsters = Box::new(WanderingMonsterEvent::new(config.spawn_density));
+	event_q.insert(wandering_monsters);
+	let age_event = Box::new(AgeEvent::new());
+	event_q.insert(age_event);
+	let mut last_tick = Instant::now();
+	let mut last_save = Instant::now();
+	let mut last_warning = None;
 	loop
 	{
-		let mut has_input = false;
-		if print_prompt
+		if stop.swap(false,Ordering::SeqCst)
 		{
-			if last_output_char != '\n'
-			{
-				stream.write_all(b"\n").unwrap();
-			}
-			stream.write_all(b">> ").unwrap();
-			stream.flush().unwrap();
-			last_output_char = ' ';
+			let seconds = if world.shutdown_at.is_none() { config.shutdown_seconds } else { 0 };
+			world.shutdown_at = Some(Instant::now()+Duration::from_secs(seconds.into()));
 		}
-		print_prompt = false;
-		let mut buf = vec![0;128];
-		let n = match stream.read(&mut buf)
+		match world.shutdown_at
 		{
-			Err(e) =>
+			Some(shutdown_at) if Instant::now() >= shutdown_at =>
 				{
-					match e.kind()
-					{
-						ErrorKind::WouldBlock => { 0 },
-						_ => { return; }
-					}
-				}
-			Ok(m) => { if m == 0 { return; } m }
-		};
-		buf.truncate(n);
-		input.extend_from_slice(&buf);
-		let end = input.last();
-		match end
-		{
-			Some(end) => if *end == b'\n' { has_input = true; },
+					shut_down(&mut world,&mut players);
+					return;
+				},
 			_ => { () }
 		}
+		// Process input until it is time for the next sub tick
+		let sub_tick_end = Instant::now()+sub_tick_duration;
+		loop
 		{
-			// Lock the world
-			let mut world = world_obj.lock().unwrap();
-			// Got the lock, make sure we are alive before processing a command
-			if check_if_removed(uuid,&mut world,&mut stream,last_message_list_read_time) { return; }
-			// Process commands
-			if has_input
+			let now = Instant::now();
+			if now >= sub_tick_end
 			{
-				// Got the input
-				has_input = false;
-				print_prompt = true;
-				last_output_char = '\n';
-				{
-					// Process the input
-					let input_string = String::from_utf8_lossy(&input);
-					let clean_input_string = input_string.trim();
-					let tokens = clean_input_string.split_whitespace();
-					for word in tokens
-					{
-						command.push_back(word.to_string());
-					}
-					let mut event_q = event_q_obj.lock().unwrap();
-					message_for_user = process_command(&mut command, uuid, &mut world, &mut event_q);
-					}
-				command.clear();
-				input.clear();
+				break;
+			}
+			match inbox.recv_timeout(sub_tick_end-now)
+			{
+				Ok(message) => { handle_client_message(message,&mut world,&mut players); },
+				Err(RecvTimeoutError::Timeout) => { break; },
+				Err(RecvTimeoutError::Disconnected) => { return; }
 			}
-			// Display any messages in the global message list
-			// Got the lock, make sure we are alive
-			if check_if_removed(uuid,&mut world,&mut stream,last_message_list_read_time) { return; }
-			let position = world.find_mobile_location(uuid).unwrap();
-			let mobile = world.fetch_mobile(uuid).unwrap();
-			mobile.save_to_file();
-			world.add_mobile(mobile,position.0,position.1);	
-			message_for_user += &world.message_list.read(position.0,position.1,uuid,last_message_list_read_time);
-			last_message_list_read_time = SystemTime::now();
 		}
-		if !message_for_user.is_empty()
+		// Run the events
+		if last_tick.elapsed() >= tick_duration
 		{
-			print_prompt = true;
-			if last_output_char != '\n'
+			last_tick = Instant::now();
+			event_q.tick(&mut world);
+			for (_,player) in players.iter_mut()
 			{
-				stream.write_all(b"\n").unwrap();
+				player.commands_this_tick = 0;
 			}
-			last_output_char = message_for_user.chars().last().unwrap();
-			stream.write_all(message_for_user.as_bytes()).unwrap();
-			stream.flush().unwrap();
-			message_for_user.clear();
 		}
+		if last_save.elapsed() >= save_duration
+		{
+			last_save = Instant::now();
+			save_players(&mut world,&players);
+		}
+		run_commands(&mut world,&mut event_q,&mut players,&config,&commands);
+		stash_link_dead(&mut world,&mut players,&config);
+		warn_of_shutdown(&mut world,&mut last_warning);
+		deliver_messages(&mut world,&mut players);
+		ask_for_passwords(&mut world,&mut players);
 	}
 }
 
-// Automatic events that run in the background
-fn background_events(world_obj: Arc<Mutex<WorldState> >, event_q_obj: Arc<Mutex<EventList> >)
+// Hold a place for a new connection, or say why there is none
+fn admit(limits: &ConnectionLimits, stream: &TcpStream) -> Result<ConnectionGuard,String>
 {
-	let tick_duration = Duration::from_millis(TICK.into());
-	{
-		let mut event_q = event_q_obj.lock().unwrap();
-		// Default events
-		let wandering_monsters = Box::new(WanderingMonsterEvent::new());
-		event_q.insert(wandering_monsters);
-		let age_event = Box::new(AgeEvent::new());
-		event_q.insert(age_event);
-	}
-	// Run the event loop
-	loop
+	match stream.peer_addr()
 	{
-		thread::sleep(tick_duration);
-		{
-			let mut event_q = event_q_obj.lock().unwrap();
-			let mut world = world_obj.lock().unwrap();
-			event_q.tick(&mut *world);
-		}
+		Ok(address) => { return limits.admit(address.ip()); },
+		Err(e) => { return Err(e.to_string()); }
 	}
 }
 
 fn main()
 {
-	let world_obj = Arc::new(Mutex::new(WorldState::new()));
-	let event_q_obj = Arc::new(Mutex::new(EventList::new()));
-	// Start the background thread
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let config = match Config::from_args(&args)
 	{
-		let world_obj = Arc::clone(&world_obj);
-		let event_q_obj = Arc::clone(&event_q_obj);
-		thread::spawn(||
+		Ok(config) => { Arc::new(config) },
+		Err(e) =>
 			{
-				background_events(world_obj,event_q_obj);
+				eprintln!("{}",e);
+				eprintln!("Usage: pgmud [--config <file>] [--<setting> <value>]...");
+				std::process::exit(1);
+			}
+	};
+	let (game, inbox) = mpsc::channel();
+	// Ask the game to stop when the process is interrupted or terminated
+	let stop = Arc::new(AtomicBool::new(false));
+	for signal in [signal_hook::consts::SIGINT,signal_hook::consts::SIGTERM]
+	{
+		signal_hook::flag::register(signal,stop.clone()).unwrap();
+	}
+	// Pick up the world where it was left
+	let mut world = WorldState::new();
+	world.save_directory = config.save_directory.clone();
+	world.load_from_file();
+	// Both listeners count against the same limits
+	let limits = ConnectionLimits::new(config.max_sessions,config.max_sessions_per_address);
+	// Accept browser connections
+	{
+		let game = game.clone();
+		let config = config.clone();
+		let limits = limits.clone();
+		let listener = TcpListener::bind(&config.websocket_address).unwrap();
+		thread::spawn(move ||
+			{
+				for stream in listener.incoming()
+				{
+					let stream = match stream
+					{
+						Ok(stream) => { stream },
+						Err(_e) => { continue; }
+					};
+					let guard = match admit(&limits,&stream)
+					{
+						Ok(guard) => { guard },
+						Err(message) => { WebSocket::refuse(stream,&message); continue; }
+					};
+					let game = game.clone();
+					let config = config.clone();
+					thread::spawn(move ||
+						{
+							let _guard = guard;
+							match WebSocket::accept(stream)
+							{
+								Ok(websocket) => { handle_connection(Box::new(websocket),game,config); },
+								Err(_e) => { return; }
+							}
+						}
+					);
+				}
 			}
 		);
 	}
-	// Accept connections
-	let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-	for stream in listener.incoming()
+	// Accept telnet connections
+	{
+		let config = config.clone();
+		let listener = TcpListener::bind(&config.telnet_address).unwrap();
+		thread::spawn(move ||
+			{
+				for stream in listener.incoming()
+				{
+					let stream = match stream
+					{
+						Ok(stream) => { stream },
+						Err(_e) => { continue; }
+					};
+					let guard = match admit(&limits,&stream)
+					{
+						Ok(guard) => { guard },
+						Err(message) => { refuse_connection(stream,&message); continue; }
+					};
+					let game = game.clone();
+					let config = config.clone();
+					thread::spawn(move ||
+						{
+							let _guard = guard;
+							handle_connection(Box::new(stream),game,config);
+						}
+					);
+				}
+			}
+		);
+	}
+	// Run the game until it is shut down
+	game_loop(world,inbox,config.clone(),stop);
+	// Give the sessions a moment to send their goodbyes
+	thread::sleep(Duration::from_millis(u64::from(config.sub_tick)*4));
+}
+
+#[cfg(test)]
+mod game_unit_test
+{
+	use super::*;
+	use crate::loopback::*;
+	use crate::mobile::Mobile;
+	use crate::items::Item;
+	use std::fs;
+
+	// Each test keeps its files in a directory of its own, so nothing
+	// in the working directory is written or removed
+	fn test_config(name: &str) -> Config
+	{
+		let directory = std::env::temp_dir().join(format!("pgmud_{}_{}",std::process::id(),name));
+		let _ = fs::remove_dir_all(&directory);
+		fs::create_dir_all(&directory).unwrap();
+		let mut config = Config::new();
+		config.save_directory = directory.to_string_lossy().to_string();
+		return config;
+	}
+
+	fn remove_test_directory(config: &Config)
 	{
-		let stream = stream.unwrap();
-		let world_obj = Arc::clone(&world_obj);
-		let event_q_obj = Arc::clone(&event_q_obj);
+		let _ = fs::remove_dir_all(&config.save_directory);
+	}
+
+	fn start_game_with(world: WorldState, config: Config) -> Sender<ClientMessage>
+	{
+		let (game, inbox) = mpsc::channel();
 		thread::spawn(||
 			{
-				handle_connection(stream,world_obj,event_q_obj);
+				game_loop(world,inbox,Arc::new(config),Arc::new(AtomicBool::new(false)));
 			}
 		);
+		return game;
+	}
+
+	fn log_in(game: &Sender<ClientMessage>, config: &Config, name: &str, password: &str) -> ScriptedClient
+	{
+		let mut client = ScriptedClient::connect(game.clone(),config.clone());
+		client.wait_for("What is your name? ");
+		client.send(name);
+		client.wait_for("Password: ");
+		client.send(password);
+		client.wait_for(">> ");
+		return client;
+	}
+
+	#[test]
+	fn login_test()
+	{
+		let config = test_config("login");
+		let game = start_game_with(WorldState::new(),config.clone());
+		let mut client = ScriptedClient::connect(game.clone(),config.clone());
+		client.create_character("Scriptlogin","letmein");
+		client.send("look;  i");
+		client.wait_for("Town of Midgaard");
+		client.wait_for("You have:");
+		client.send("quit");
+		assert!(client.wait_for_close().contains("Goodbye!"));
+		// Come back with the wrong and then the right password
+		let mut client = ScriptedClient::connect(game.clone(),config.clone());
+		client.wait_for("What is your name? ");
+		client.send("Scriptlogin");
+		client.wait_for("Password: ");
+		client.send("letmeout");
+		client.wait_for("Wrong password.");
+		client.send("letmein");
+		client.wait_for(">> ");
+		client.send("loo");
+		client.wait_for("Scriptlogin is here.");
+		client.send("he");
+		client.wait_for("Here you can:");
+		// The prompt is kept with the character
+		client.send("prompt [%c %t] %x xp>");
+		client.wait_for("Prompt set.\n[0,0 Town] 0 xp> ");
+		client.send("quit");
+		client.wait_for_close();
+		let mut client = log_in(&game,&config,"Scriptlogin","letmein");
+		client.send("prompt");
+		client.wait_for("Your prompt is: [%c %t] %x xp>\n");
+		client.wait_for("[0,0 Town] 0 xp> ");
+		client.send("prompt default");
+		client.wait_for("Prompt set.\n>> ");
+		client.send("quit");
+		client.wait_for_close();
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn password_test()
+	{
+		let mut config = test_config("password");
+		config.set("admins","Scriptunlock").unwrap();
+		let game = start_game_with(WorldState::new(),config.clone());
+		let mut client = ScriptedClient::connect(game.clone(),config.clone());
+		client.create_character("Scriptpassword","letmein");
+		client.send("password");
+		client.wait_for("Old password: ");
+		client.send("letmeout");
+		client.wait_for("Wrong password.");
+		client.send("password");
+		client.wait_for("Old password: ");
+		client.send("letmein");
+		client.wait_for("Choose a password: ");
+		client.send("open sesame");
+		client.wait_for("Repeat the password: ");
+		client.send("open sesame");
+		client.wait_for("Password changed.");
+		client.send("quit");
+		client.wait_for_close();
+		let mut client = log_in(&game,&config,"Scriptpassword","open sesame");
+		client.send("quit");
+		client.wait_for_close();
+		// A character saved before there were accounts is locked
+		Mobile::new_character(&"Scriptlegacy".to_string()).save_to_file(&config.save_directory);
+		let mut client = ScriptedClient::connect(game.clone(),config.clone());
+		client.wait_for("What is your name? ");
+		client.send("Scriptlegacy");
+		assert!(client.wait_for_close().contains("Ask an admin to set one."));
+		let mut admin = ScriptedClient::connect(game.clone(),config.clone());
+		admin.create_character("Scriptunlock","letmein");
+		admin.send("setpassword Scriptnobody");
+		admin.wait_for("There is no character called Scriptnobody.");
+		admin.send("setpassword Scriptlegacy");
+		admin.wait_for("Choose a password: ");
+		admin.send("unlocked");
+		admin.wait_for("Repeat the password: ");
+		admin.send("unlocked");
+		admin.wait_for("Password changed.");
+		let mut client = log_in(&game,&config,"Scriptlegacy","unlocked");
+		client.send("quit");
+		client.wait_for_close();
+		admin.send("quit");
+		admin.wait_for_close();
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn reconnect_test()
+	{
+		let mut config = test_config("reconnect");
+		config.link_dead_seconds = 1;
+		let game = start_game_with(WorldState::new(),config.clone());
+		let mut first = ScriptedClient::connect(game.clone(),config.clone());
+		first.create_character("Scriptlink","letmein");
+		let mut watcher = ScriptedClient::connect(game.clone(),config.clone());
+		watcher.create_character("Scriptwatch","letmein");
+		// Logging in again takes over the body
+		let mut second = log_in(&game,&config,"Scriptlink","letmein");
+		assert!(first.wait_for_close().contains("Someone else has logged in as you."));
+		second.wait_for("Reconnected.");
+		second.send("look");
+		let room = second.wait_for("Scriptwatch is here.");
+		assert_eq!(room.matches("Scriptlink is here.").count(),1);
+		// Dropping the connection leaves the body for a while
+		drop(second);
+		watcher.wait_for("Scriptlink has lost their link.");
+		let mut third = log_in(&game,&config,"Scriptlink","letmein");
+		third.wait_for("Reconnected.");
+		watcher.wait_for("Scriptlink has reconnected.");
+		// Then it is stashed when the grace period is over
+		drop(third);
+		watcher.wait_for("Scriptlink fades away.");
+		watcher.send("look");
+		assert!(!watcher.wait_for("Scriptwatch is here.").contains("Scriptlink is here."));
+		let mut fourth = log_in(&game,&config,"Scriptlink","letmein");
+		fourth.send("look");
+		fourth.wait_for("Scriptlink is here.");
+		fourth.send("quit");
+		fourth.wait_for_close();
+		watcher.send("quit");
+		watcher.wait_for_close();
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn shutdown_test()
+	{
+		let mut config = test_config("shutdown");
+		config.set("admins","Scriptadmin").unwrap();
+		let game = start_game_with(WorldState::new(),config.clone());
+		let mut admin = ScriptedClient::connect(game.clone(),config.clone());
+		admin.create_character("Scriptadmin","letmein");
+		let mut player = ScriptedClient::connect(game.clone(),config.clone());
+		player.create_character("Scriptplayer","letmein");
+		player.send("shutdown");
+		player.wait_for("What?");
+		admin.send("shutdown 30");
+		admin.wait_for("Shutting down in 30 seconds.");
+		player.wait_for("The server will shut down in 30 seconds.");
+		admin.send("shutdown cancel");
+		player.wait_for("The shutdown has been cancelled.");
+		admin.send("shutdown 1");
+		player.wait_for("The server will shut down in 1 second.");
+		assert!(player.wait_for_close().contains("The server is shutting down. Goodbye!"));
+		admin.wait_for_close();
+		let mut world = WorldState::new();
+		world.save_directory = config.save_directory.clone();
+		assert!(world.load_from_file());
+		assert!(std::path::Path::new(&config.save_directory).join("scriptplayer.csv").exists());
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn save_test()
+	{
+		let mut config = test_config("save");
+		config.save_seconds = 1;
+		let game = start_game_with(WorldState::new(),config.clone());
+		let mut client = ScriptedClient::connect(game.clone(),config.clone());
+		client.create_character("Scriptsave","letmein");
+		client.send("e");
+		client.wait_for("In the");
+		// The character is saved while it plays, not only when it leaves
+		thread::sleep(Duration::from_millis(1500));
+		let mut saved = Mobile::new_character(&"Scriptsave".to_string());
+		assert!(saved.load_from_file(&config.save_directory));
+		assert!(saved.visited.contains(&(1,0)));
+		client.send("quit");
+		client.wait_for_close();
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn flood_test()
+	{
+		let mut config = test_config("flood");
+		config.commands_per_second = 3;
+		let game = start_game_with(WorldState::new(),config.clone());
+		let mut client = ScriptedClient::connect(game.clone(),config.clone());
+		client.create_character("Scriptflood","letmein");
+		for _ in 0..5
+		{
+			client.send("look");
+		}
+		client.wait_for("You are sending commands too quickly.");
+		thread::sleep(Duration::from_millis(1100));
+		for _ in 0..5
+		{
+			client.send("look");
+		}
+		assert!(client.wait_for_close().contains("You have been disconnected for sending commands too quickly."));
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn login_timeout_test()
+	{
+		let mut config = test_config("login_timeout");
+		config.login_seconds = 1;
+		let game = start_game_with(WorldState::new(),config.clone());
+		let mut idle = ScriptedClient::connect(game.clone(),config.clone());
+		idle.wait_for("What is your name? ");
+		// Stopping part way through is no better than saying nothing
+		let mut slow = ScriptedClient::connect(game.clone(),config.clone());
+		slow.wait_for("What is your name? ");
+		slow.send("Scriptslow");
+		slow.wait_for("Keep this character (y/n)? ");
+		idle.wait_for_close();
+		slow.wait_for_close();
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn talk_test()
+	{
+		let config = test_config("talk");
+		let game = start_game_with(WorldState::new(),config.clone());
+		let mut speaker = ScriptedClient::connect(game.clone(),config.clone());
+		speaker.create_character("Scripttalk","letmein");
+		let mut listener = ScriptedClient::connect(game.clone(),config.clone());
+		listener.create_character("Scripthear","letmein");
+		speaker.send("say   Hello there,  {r}friend ");
+		speaker.wait_for("You say, 'Hello there,  {r}friend'");
+		listener.wait_for("Scripttalk says, 'Hello there,  {r}friend'");
+		speaker.send("hide");
+		speaker.wait_for("You find a place to hide.");
+		speaker.send("look");
+		assert!(!speaker.wait_for("Town of Midgaard").contains("You stop hiding."));
+		speaker.send("say Boo");
+		speaker.wait_for("You stop hiding.");
+		listener.wait_for("Scripttalk says, 'Boo'");
+		speaker.send("emote waves happily.");
+		speaker.wait_for("Scripttalk waves happily.");
+		listener.wait_for("Scripttalk waves happily.");
+		speaker.send("smile scripthear");
+		speaker.wait_for("You smile at Scripthear.");
+		let seen = listener.wait_for("Scripttalk smiles at you.");
+		assert!(!seen.contains("smiles at Scripthear"));
+		speaker.send("tell");
+		speaker.wait_for("Tell whom what?");
+		speaker.send("tell nobody hi");
+		speaker.wait_for("Nobody called nobody is playing.");
+		// Tells and shouts reach players elsewhere
+		listener.send("e");
+		speaker.wait_for("Scripthear leaves to the east.");
+		listener.send("e");
+		listener.wait_for("In the");
+		// The map shows the way back to town
+		listener.send("map 5");
+		let map = listener.wait_for("@ you");
+		assert!(map.lines().any(|line| line.starts_with('T') && line.chars().nth(2) == Some('@')));
+		speaker.send("tell SCRIPTHEAR meet me in town");
+		speaker.wait_for("You tell Scripthear, 'meet me in town'");
+		listener.wait_for("Scripttalk tells you, 'meet me in town'");
+		speaker.send("who");
+		let list = speaker.wait_for("players.");
+		assert!(list.contains("Scripttalk") && list.contains("Scripthear"));
+		speaker.send("shout Over here!");
+		listener.wait_for("Scripttalk shouts, 'Over here!'");
+		speaker.send("say Nobody hears this");
+		speaker.wait_for("You say, 'Nobody hears this'");
+		listener.send("say Done");
+		assert!(!listener.wait_for("You say, 'Done'").contains("Nobody hears this"));
+		speaker.send("quit");
+		speaker.wait_for_close();
+		listener.send("quit");
+		listener.wait_for_close();
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn trade_test()
+	{
+		let config = test_config("trade");
+		let mut world = WorldState::new();
+		world.add_item(0,0,Item::sword());
+		world.add_item(0,0,Item::rabbit_foot());
+		let game = start_game_with(world,config.clone());
+		let mut buyer = ScriptedClient::connect(game.clone(),config.clone());
+		buyer.create_character("Scriptbuyer","letmein");
+		let mut seller = ScriptedClient::connect(game.clone(),config.clone());
+		seller.create_character("Scriptseller","letmein");
+		buyer.send("get foot");
+		buyer.wait_for("Got it!");
+		seller.send("get sword");
+		seller.wait_for("Got it!");
+		seller.send("offer sword");
+		seller.wait_for("You aren't trading with anyone.");
+		seller.send("trade scriptbuyer");
+		seller.wait_for("You ask Scriptbuyer to trade.");
+		buyer.wait_for("Scriptseller wants to trade with you.");
+		buyer.send("trade scriptseller");
+		buyer.wait_for("You are trading with Scriptseller.");
+		seller.wait_for("Scriptbuyer agrees to trade.");
+		seller.send("offer sword");
+		seller.wait_for("You offer the sword.");
+		buyer.wait_for("Scriptseller offers a sword.");
+		seller.send("offer sword");
+		seller.wait_for("You have already offered that.");
+		seller.send("accept");
+		seller.wait_for("You accept the trade.");
+		// A new offer has to be accepted again
+		buyer.send("offer foot");
+		buyer.wait_for("You offer the rabbit foot.");
+		buyer.send("accept");
+		buyer.wait_for("You accept the trade.");
+		seller.send("accept");
+		seller.wait_for("The trade is done.");
+		buyer.wait_for("The trade is done.");
+		buyer.send("i");
+		buyer.wait_for("sword");
+		buyer.send("give sword nobody");
+		buyer.wait_for("Give it to whom?");
+		buyer.send("give sword scriptseller");
+		buyer.wait_for("You give the sword to Scriptseller.");
+		seller.wait_for("Scriptbuyer gives you a sword.");
+		seller.send("quit");
+		seller.wait_for_close();
+		buyer.send("quit");
+		buyer.wait_for_close();
+		remove_test_directory(&config);
+	}
+
+	#[test]
+	fn combat_test()
+	{
+		let config = test_config("combat");
+		let mut world = WorldState::new();
+		world.add_mobile(Mobile::rabbit(),0,1);
+		let game = start_game_with(world,config.clone());
+		let mut client = ScriptedClient::connect(game.clone(),config.clone());
+		client.create_character("Scriptfight","letmein");
+		client.send("n");
+		client.wait_for("A rabbit watches you carefully.");
+		client.send("kill rabbit");
+		// The room text already named the rabbit, so wait for the fight itself
+		client.wait_for_any(&["wounds","repulses","slays","eludes"]);
+		client.send("color on");
+		client.wait_for("Color is \x1b[1;32mon\x1b[0m.");
+		client.send("look");
+		client.wait_for("\x1b[1;36mIn the");
+		client.send("quit");
+		client.wait_for_close();
+		remove_test_directory(&config);
 	}
 }
@@ -1,8 +1,10 @@
-use crate::location::Location;
-use crate::location::LocationTypeCode;
+use crate::location::{Location,LocationTypeCode,Exit};
+use crate::mobile::Mobile;
 use crate::dice::*;
 use crate::Object;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap,BTreeSet};
+use std::path::Path;
+use crate::items::Item;
 use crate::message::*;
 
 pub trait LocationVisitor
@@ -55,12 +57,12 @@ impl Map
 		}
 	}
 
-	pub fn get_location_description(&self, x: i16, y: i16) -> String
+	pub fn get_location_description(&self, x: i16, y: i16, viewer: usize) -> String
 	{
 		let location = self.location_by_position.get(&(x,y));
 		match location
 		{
-			Some(location) => { return location.description(); },
+			Some(location) => { return location.description_seen_by(Some(viewer)); },
 			_ => { return "Unexplored".to_string(); }
 		}
 	}
@@ -100,6 +102,12 @@ impl Map
 	}
 
 	fn make_new_location(&mut self, x: i16, y: i16) -> Box<Location>
+	{
+		let location = self.make_new_terrain(x,y);
+		return Map::hide_things(location);
+	}
+
+	fn make_new_terrain(&mut self, x: i16, y: i16) -> Box<Location>
 	{
 		let d8 = Dice { number: 1, die: 8 };
 		let deep_woods_count = self.count_adjacent(x,y,LocationTypeCode::DeepWoods);
@@ -132,6 +140,112 @@ impl Map
 		}
 	}
 
+	// Now and then a new location has a cache of treasure hidden in it,
+	// and far from town there may be a hidden tunnel that leads back
+	fn hide_things(mut location: Box<Location>) -> Box<Location>
+	{
+		let d20 = Dice { number: 1, die: 20 };
+		if d20.roll() == 1
+		{
+			let mut cache = Item::minor_treasure().unwrap_or(Item::uncut_precious_stone());
+			cache.hidden = Mobile::routine_task();
+			location.add_item(cache);
+		}
+		let distance = location.x.abs() + location.y.abs();
+		if location.location_type == LocationTypeCode::Hills && distance > 5 && d20.roll() == 1
+		{
+			location.add_exit(Exit::new("down",(0,0),"a narrow tunnel",Mobile::skilled_task()));
+		}
+		return location;
+	}
+
+	// Those who have found something hidden, separated by spaces
+	fn ids_to_string(ids: &BTreeSet<usize>) -> String
+	{
+		return ids.iter().map(|id| id.to_string()).collect::<Vec<String> >().join(" ");
+	}
+
+	fn ids_from_string(ids: &str) -> Option<BTreeSet<usize> >
+	{
+		return ids.split_whitespace().map(|id| id.parse::<usize>().ok()).collect();
+	}
+
+	// Save the explored locations and the items lying in them
+	pub fn save_to_file(&self, file_name: &Path) -> csv::Result<()>
+	{
+		let mut wtr = csv::WriterBuilder::new().flexible(true).from_path(file_name)?;
+		for (_,location) in self.location_by_position.iter()
+		{
+			let x = location.x.to_string();
+			let y = location.y.to_string();
+			wtr.write_record(&["location",&x,&y,location.location_type.name(),&location.get_name()])?;
+			for item in location.items().iter()
+			{
+				wtr.write_record(&["item",&x,&y,item.type_name(),&item.lifetime.to_string(),&item.name,&item.description,&item.hidden.to_string(),&Map::ids_to_string(&item.found_by)])?;
+			}
+			for exit in location.exits().iter()
+			{
+				wtr.write_record(&["exit",&x,&y,&exit.direction,&exit.to.0.to_string(),&exit.to.1.to_string(),&exit.name,&exit.hidden.to_string(),&Map::ids_to_string(&exit.found_by)])?;
+			}
+		}
+		wtr.flush()?;
+		return Ok(());
+	}
+
+	// Load a saved map. Returns None if the file is missing or damaged.
+	pub fn load_from_file(file_name: &Path) -> Option<Map>
+	{
+		let mut reader = csv::ReaderBuilder::new()
+			.has_headers(false)
+			.flexible(true)
+			.from_path(file_name)
+			.ok()?;
+		let mut map = Map { location_by_position: BTreeMap::new() };
+		for record in reader.records()
+		{
+			let record = record.ok()?;
+			let x = record.get(1)?.parse::<i16>().ok()?;
+			let y = record.get(2)?.parse::<i16>().ok()?;
+			match record.get(0)?
+			{
+				"location" =>
+					{
+						let code = LocationTypeCode::from_name(record.get(3)?)?;
+						let mut location = Box::new(Location::new(x,y,code,record.get(4)?.to_string()));
+						// The saved items replace the ones a new location starts with
+						location.clear_items();
+						map.location_by_position.insert((x,y),location);
+					},
+				"item" =>
+					{
+						let mut item = Item::from_type_name(record.get(3)?)?;
+						item.lifetime = record.get(4)?.parse::<u32>().ok()?;
+						item.name = record.get(5)?.to_string();
+						item.description = record.get(6)?.to_string();
+						// Older files don't say whether an item is hidden
+						item.hidden = record.get(7).unwrap_or("0").parse::<i16>().ok()?;
+						item.found_by = Map::ids_from_string(record.get(8).unwrap_or(""))?;
+						map.location_by_position.get_mut(&(x,y))?.add_item(item);
+					},
+				"exit" =>
+					{
+						let to_x = record.get(4)?.parse::<i16>().ok()?;
+						let to_y = record.get(5)?.parse::<i16>().ok()?;
+						let hidden = record.get(7)?.parse::<i16>().ok()?;
+						let mut exit = Exit::new(record.get(3)?,(to_x,to_y),record.get(6)?,hidden);
+						exit.found_by = Map::ids_from_string(record.get(8)?)?;
+						map.location_by_position.get_mut(&(x,y))?.add_exit(exit);
+					},
+				_ => { return None; }
+			}
+		}
+		if !map.location_by_position.contains_key(&(0,0))
+		{
+			return None;
+		}
+		return Some(map);
+	}
+
 	pub fn visit_all_locations(&mut self, visitor: &mut impl LocationVisitor, messages: &mut MessageList)
 	{
 		for (_,mut location) in self.location_by_position.iter_mut()
@@ -161,17 +275,52 @@ impl Map
 		{
 			for x in x_min..x_max+1
 			{
-				let location = self.get_location_type(x, y);
-				match location
+				result.push(Map::glyph(self.get_location_type(x, y)));
+			}
+			result.push_str("\n");
+		}
+		return result;
+	}
+
+	fn glyph(location_type: LocationTypeCode) -> char
+	{
+		match location_type
+		{
+			LocationTypeCode::Town => { return 'T'; },
+			LocationTypeCode::Forest => { return '-'; },
+			LocationTypeCode::DeepWoods => { return '*'; },
+			LocationTypeCode::Hills => { return '^'; },
+			LocationTypeCode::Unexplored => { return ' '; }
+		}
+	}
+
+	// Draw the square around (x,y) that reaches radius locations out
+	// from it, with north at the top. Only the visited locations are
+	// shown, and (x,y) itself is marked with @.
+	pub fn draw_window(&self, x: i16, y: i16, radius: i16, visited: &BTreeSet<(i16,i16)>) -> String
+	{
+		let mut result = String::new();
+		for row in (y-radius..=y+radius).rev()
+		{
+			for column in x-radius..=x+radius
+			{
+				if column == x && row == y
+				{
+					result.push('@');
+				}
+				else if visited.contains(&(column,row))
 				{
-					LocationTypeCode::Town => result.push_str("T"),
-					LocationTypeCode::Forest => result.push_str("-"),
-					LocationTypeCode::DeepWoods => result.push_str("*"),
-					LocationTypeCode::Hills => result.push_str("^"),
-					LocationTypeCode::Unexplored => result.push_str(" "),
+					result.push(Map::glyph(self.get_location_type(column,row)));
+				}
+				else
+				{
+					result.push(' ');
 				}
 			}
-			result.push_str("\n");
+			// Don't send trailing spaces
+			let end = result.trim_end_matches(' ').len();
+			result.truncate(end);
+			result.push('\n');
 		}
 		return result;
 	}
@@ -205,6 +354,62 @@ mod map_unit_test
 		}
 	}
 
+	#[test]
+	fn save_load_map()
+	{
+		let file_name = Path::new("map_test.csv");
+		let mut map = Map::new();
+		let mut location = map.fetch(1,0);
+		location.clear_items();
+		location.add_item(Item::corpse("rabbit".to_string()));
+		let mut sword = Item::sword();
+		sword.hidden = 50;
+		sword.found_by.insert(7);
+		sword.found_by.insert(42);
+		location.add_item(sword);
+		location.add_exit(Exit::new("down",(0,0),"a narrow tunnel",60));
+		map.replace(location);
+		map.save_to_file(file_name).unwrap();
+		let loaded = Map::load_from_file(file_name);
+		let _ = std::fs::remove_file(file_name);
+		let mut loaded = loaded.unwrap();
+		assert_eq!(loaded.number_of_locations(),2);
+		assert!(loaded.get_location_type(1,0) == map.get_location_type(1,0));
+		let location = loaded.fetch(1,0);
+		assert_eq!(location.items().len(),2);
+		assert_eq!(location.items()[0].name,"rabbit corpse");
+		assert_eq!(location.items()[0].description,"A dead rabbit is here.");
+		assert_eq!(location.items()[1].name,"sword");
+		assert_eq!(location.items()[1].hidden,50);
+		assert!(location.items()[1].is_visible_to(42));
+		assert!(!location.items()[1].is_visible_to(8));
+		assert_eq!(location.exits().len(),1);
+		assert_eq!(location.exits()[0].to,(0,0));
+		assert_eq!(location.exits()[0].name,"a narrow tunnel");
+		assert_eq!(location.exits()[0].hidden,60);
+		assert!(location.exits()[0].found_by.is_empty());
+		assert!(Map::load_from_file(Path::new("no_such_map.csv")).is_none());
+	}
+
+	#[test]
+	fn draw_window_test()
+	{
+		let mut map = Map::new();
+		for (x,y,location_type) in [(1,0,LocationTypeCode::Forest),(0,1,LocationTypeCode::Hills),(-1,-1,LocationTypeCode::DeepWoods)]
+		{
+			let mut location = map.fetch(x,y);
+			location.location_type = location_type;
+			map.replace(location);
+		}
+		let mut visited = BTreeSet::new();
+		visited.insert((0,0));
+		visited.insert((0,1));
+		visited.insert((-1,-1));
+		// The forest was never visited so it is not shown
+		assert_eq!(map.draw_window(1,0,1,&visited),"^\nT@\n\n");
+		assert_eq!(map.draw_window(0,0,1,&visited)," ^\n @\n*\n");
+	}
+
 	#[test]
 	fn random_walk_map()
 	{
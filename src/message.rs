@@ -7,6 +7,12 @@ struct Message
 	y: i16,
 	uuid: Option<usize>,
 	uuid_is_target: bool,
+	// Everyone gets this message wherever they are
+	everyone: bool,
+	// How far away the message can be heard
+	radius: i16,
+	// Someone else who does not get this message
+	other: Option<usize>,
 	message: String,
 	posted_time: SystemTime
 }
@@ -30,6 +36,9 @@ impl MessageList
 			y: y,
 			uuid: None,
 			uuid_is_target: false,
+			everyone: false,
+			radius: 0,
+			other: None,
 			message: msg,
 			posted_time: SystemTime::now()
 		};
@@ -61,6 +70,9 @@ impl MessageList
 			y: y,
 			uuid: None,
 			uuid_is_target: false,
+			everyone: false,
+			radius: 0,
+			other: None,
 			message: msg,
 			posted_time: SystemTime::now()
 		};
@@ -76,6 +88,45 @@ impl MessageList
 			y: y,
 			uuid: Some(origin),
 			uuid_is_target: false,
+			everyone: false,
+			radius: 0,
+			other: None,
+			message: msg,
+			posted_time: SystemTime::now()
+		};
+		self.msg_list.push_back(global_msg);
+		self.cleanup_old_messages();
+	}
+
+	// Post to a location for everyone but the two mobiles involved
+	pub fn post_for_bystanders(&mut self, msg: String, x: i16, y: i16, origin: usize, target: usize)
+	{
+		let global_msg = Message {
+			x: x,
+			y: y,
+			uuid: Some(origin),
+			uuid_is_target: false,
+			everyone: false,
+			radius: 0,
+			other: Some(target),
+			message: msg,
+			posted_time: SystemTime::now()
+		};
+		self.msg_list.push_back(global_msg);
+		self.cleanup_old_messages();
+	}
+
+	// Post to every location within the radius, except to the origin
+	pub fn post_within(&mut self, msg: String, x: i16, y: i16, radius: i16, origin: usize)
+	{
+		let global_msg = Message {
+			x: x,
+			y: y,
+			uuid: Some(origin),
+			uuid_is_target: false,
+			everyone: false,
+			radius: radius,
+			other: None,
 			message: msg,
 			posted_time: SystemTime::now()
 		};
@@ -91,6 +142,26 @@ impl MessageList
 			y: 0,
 			uuid: Some(target),
 			uuid_is_target: true,
+			everyone: false,
+			radius: 0,
+			other: None,
+			message: msg,
+			posted_time: SystemTime::now()
+		};
+		self.msg_list.push_back(global_msg);
+		self.cleanup_old_messages();
+	}
+
+	pub fn post_for_everyone(&mut self, msg: String)
+	{
+		let global_msg = Message {
+			x: 0,
+			y: 0,
+			uuid: None,
+			uuid_is_target: false,
+			everyone: true,
+			radius: 0,
+			other: None,
 			message: msg,
 			posted_time: SystemTime::now()
 		};
@@ -121,11 +192,11 @@ impl MessageList
 		{
 			if element.posted_time >= after
 			{
-				if element.uuid_is_target && element.uuid.unwrap() == reader
+				if element.everyone || (element.uuid_is_target && element.uuid.unwrap() == reader)
 				{
 					result += &element.message; result += "\n";
 				}
-				else if !element.uuid_is_target && element.x == x && element.y == y
+				else if !element.uuid_is_target && (element.x-x).abs() <= element.radius && (element.y-y).abs() <= element.radius && element.other != Some(reader)
 				{
 					match element.uuid
 					{
@@ -184,6 +255,32 @@ mod messages_unit_test
 		assert!(result.is_empty());
 	}
 
+	#[test]
+	fn radius_test()
+	{
+		let now = SystemTime::now();
+		let origin = uid::new();
+		let target = uid::new();
+		let mut msg_list = MessageList::new();
+		msg_list.post_within("shout!".to_string(),0,0,2,origin);
+		msg_list.post_for_bystanders("wave".to_string(),0,0,origin,target);
+		assert_eq!(msg_list.read(-2,1,uid::new(),now),"shout!\n".to_string());
+		assert!(msg_list.read(3,0,uid::new(),now).is_empty());
+		assert!(msg_list.read(0,0,origin,now).is_empty());
+		assert_eq!(msg_list.read(0,0,target,now),"shout!\n".to_string());
+		assert_eq!(msg_list.read(0,0,uid::new(),now),"shout!\nwave\n".to_string());
+	}
+
+	#[test]
+	fn everyone_test()
+	{
+		let now = SystemTime::now();
+		let mut msg_list = MessageList::new();
+		msg_list.post_for_everyone("test!".to_string());
+		assert_eq!(msg_list.read(5,-3,uid::new(),now),"test!\n".to_string());
+		assert!(msg_list.read_targetted(uid::new(),now).is_empty());
+	}
+
 	#[test]
 	fn no_echo_test()
 	{
@@ -2,7 +2,10 @@ use crate::object::Object;
 use crate::items::*;
 use crate::dice::*;
 use crate::uid;
+use crate::prompt::DEFAULT_PROMPT;
 use std::cmp::max;
+use std::collections::BTreeSet;
+use std::path::{Path,PathBuf};
 
 // A mobile object or creature
 pub struct Mobile
@@ -48,12 +51,13 @@ pub struct Mobile
 	pub armor: i16,
 	// Inventory
 	pub inventory: Vec<Box<Item > >,
-	// Maximum miscellaneous that can be carried
+	// How many more items can be carried
 	pub misc_items_slots: u8,
 	// Has a weapon?
 	pub is_armed: bool,
-	// Wearing armor?
-	pub is_armored: bool,
+	// Equipment. These don't take up inventory slots.
+	pub weapon: Option<Box<Item> >,
+	pub worn_armor: Option<Box<Item> >,
 	// Target for knowledge rolls
 	pub frequency: i16,
 	// Does this mobile wander about of its own accord?
@@ -61,7 +65,21 @@ pub struct Mobile
 	// Is this mobile aggressive?
 	pub aggressive: bool,
 	// Does this mobile collect things?
-	pub collects: bool
+	pub collects: bool,
+	// Everyone we are in a fight with
+	pub fighting: BTreeSet<usize>,
+	// Hiding stays in effect until the mobile does something
+	pub hidden: bool,
+	// Those who saw where we hid. They keep seeing us until we come out.
+	pub spotted_by: BTreeSet<usize>,
+	// Sneaking hides comings and goings from those who don't notice
+	pub sneaking: bool,
+	// Does the player want colour?
+	pub color: bool,
+	// The player's prompt template
+	pub prompt: String,
+	// The locations that the player has been to
+	pub visited: BTreeSet<(i16,i16)>
 }
 
 impl Object for Mobile
@@ -109,11 +127,17 @@ impl Object for Mobile
 impl Mobile
 {
 
-	pub fn load_from_file(&mut self) -> bool 
+	// Characters are saved as <name>.csv in the save directory
+	fn file_name(&self, directory: &str, extension: &str) -> PathBuf
 	{
-		let mut file_name = self.name.clone()+&".csv".to_string();
+		let mut file_name = self.name.clone()+extension;
 		file_name.make_ascii_lowercase();
-    	let result = csv::Reader::from_path(file_name);
+		return Path::new(directory).join(file_name);
+	}
+
+	pub fn load_from_file(&mut self, directory: &str) -> bool 
+	{
+    	let result = csv::Reader::from_path(self.file_name(directory,".csv"));
 		match result
 		{
 			Ok(mut reader) => 
@@ -145,6 +169,17 @@ impl Mobile
 										"metalwork" => { self.metalwork = value.parse::<i16>().unwrap(); },
 										"woodcraft" => { self.woodcraft = value.parse::<i16>().unwrap(); },
 										"id" => { self.id = value.parse::<usize>().unwrap(); },
+										"color" => { self.color = value.parse::<bool>().unwrap_or(false); },
+										"prompt" => { self.prompt = value.to_string(); },
+										"visited" => { self.visited = Mobile::parse_visited(value); },
+										"weapon" | "armor" =>
+											{
+												match Item::from_type_name(value)
+												{
+													Some(item) => { self.equip(item); },
+													None => { () }
+												}
+											},
 										_ => { () }
 									}
 								},
@@ -157,11 +192,30 @@ impl Mobile
 		return true;
 	}
 
-	pub fn save_to_file(&self)
+	// The visited locations are saved as x:y pairs separated by spaces
+	fn format_visited(&self) -> String
 	{
-		let mut file_name = self.name.clone()+&".csv".to_string();
-		file_name.make_ascii_lowercase();
-		let mut wtr = csv::Writer::from_path(file_name).unwrap();
+		let pairs: Vec<String> = self.visited.iter().map(|(x,y)| x.to_string()+":"+&y.to_string()).collect();
+		return pairs.join(" ");
+	}
+
+	fn parse_visited(value: &str) -> BTreeSet<(i16,i16)>
+	{
+		let mut visited = BTreeSet::new();
+		for pair in value.split_whitespace()
+		{
+			match pair.split_once(':').map(|(x,y)| (x.parse::<i16>(),y.parse::<i16>()))
+			{
+				Some((Ok(x),Ok(y))) => { visited.insert((x,y)); },
+				_ => { () }
+			}
+		}
+		return visited;
+	}
+
+	pub fn save_to_file(&self, directory: &str)
+	{
+		let mut wtr = csv::Writer::from_path(self.file_name(directory,".csv")).unwrap();
 		let _ = wtr.write_record(&["name",&self.name]).unwrap();
 		let _ = wtr.write_record(&["id",&self.id.to_string()]).unwrap();
 		let _ = wtr.write_record(&["str",&self.strength.to_string()]).unwrap();
@@ -181,6 +235,17 @@ impl Mobile
 		let _ = wtr.write_record(&["woodcraft",&self.woodcraft.to_string()]).unwrap();
 		let _ = wtr.write_record(&["knowledge",&self.knowledge.to_string()]).unwrap();
 		let _ = wtr.write_record(&["stealth",&self.stealth.to_string()]).unwrap();
+		let _ = wtr.write_record(&["color",&self.color.to_string()]).unwrap();
+		let _ = wtr.write_record(&["prompt",&self.prompt]).unwrap();
+		let _ = wtr.write_record(&["visited",&self.format_visited()]).unwrap();
+		// Equipment is saved by type, as it is made fresh when loaded
+		for (slot,item) in [("weapon",&self.weapon),("armor",&self.worn_armor)]
+		{
+			if item.is_some()
+			{
+				let _ = wtr.write_record(&[slot,item.as_ref().unwrap().type_name()]).unwrap();
+			}
+		}
 		let _ = wtr.flush().unwrap();
 	}
 
@@ -198,12 +263,10 @@ impl Mobile
 		return damage_applied;
 	}
 
-	pub fn is_killed(&self)
+	pub fn is_killed(&self, directory: &str)
 	{
-		let mut old_file_name = self.name.clone()+&".csv".to_string();
-		old_file_name.make_ascii_lowercase();
-		let mut new_file_name = self.name.clone()+&".dead".to_string();
-		new_file_name.make_ascii_lowercase();
+		let old_file_name = self.file_name(directory,".csv");
+		let new_file_name = self.file_name(directory,".dead");
 		let _ignore_result = std::fs::rename(old_file_name,new_file_name);
 	}
 
@@ -324,12 +387,154 @@ impl Mobile
 		let mut result = String::new();
 		for item in self.inventory.iter()
 		{
+			result += "{y}";
 			result += &item.get_name();
-			result += "\n";
+			result += "{x}\n";
+		}
+		return result;
+	}
+
+	pub fn list_equipment(&self) -> String
+	{
+		let mut result = String::new();
+		for (slot,item) in [("wielded",&self.weapon),("worn",&self.worn_armor)]
+		{
+			result += slot;
+			result += ": ";
+			match item
+			{
+				Some(item) => { result += &("{y}".to_string()+&item.get_name()+"{x}\n"); },
+				None => { result += "nothing\n"; }
+			}
+		}
+		return result;
+	}
+
+	// Take an item out of its equipment slot
+	fn take_off(&mut self, slot_code: ItemCategoryCode) -> Option<Box<Item> >
+	{
+		match slot_code
+		{
+			ItemCategoryCode::Weapon =>
+				{
+					let item = self.weapon.take()?;
+					self.is_armed = false;
+					self.unwield();
+					return Some(item);
+				},
+			ItemCategoryCode::Armor =>
+				{
+					let item = self.worn_armor.take()?;
+					self.armor -= item.armor_value;
+					return Some(item);
+				},
+			ItemCategoryCode::Misc => { return None; }
+		}
+	}
+
+	// Put an item into its equipment slot, which must be empty. Things
+	// that can't be equipped go into the inventory.
+	fn put_on(&mut self, item: Box<Item>)
+	{
+		match item.category_code
+		{
+			ItemCategoryCode::Weapon =>
+				{
+					self.wielded = item.name.clone();
+					self.damage_dice = item.weapon_dice();
+					self.is_armed = true;
+					self.weapon = Some(item);
+				},
+			ItemCategoryCode::Armor =>
+				{
+					self.armor += item.armor_value;
+					self.worn_armor = Some(item);
+				},
+			ItemCategoryCode::Misc => { self.add_item(item,false); }
+		}
+	}
+
+	// Equip an item that isn't in the inventory, such as the gear that
+	// monsters start with
+	pub fn equip(&mut self, item: Box<Item>)
+	{
+		let old = self.take_off(item.category_code);
+		self.put_on(item);
+		if old.is_some()
+		{
+			self.add_item(old.unwrap(),false);
+		}
+	}
+
+	// Move an item from the inventory into its equipment slot. Whatever
+	// was there before goes back into the inventory.
+	fn equip_item_by_name(&mut self, key: &String, slot_code: ItemCategoryCode, verb: &str) -> String
+	{
+		let pos = self.find_item_by_name(key);
+		if pos.is_none()
+		{
+			return "You aren't carrying that.".to_string();
+		}
+		let pos = pos.unwrap();
+		if self.inventory[pos].category_code != slot_code
+		{
+			return "You can't ".to_string()+verb+" that.";
 		}
+		let item = self.fetch_item_by_position(pos).unwrap();
+		let result = "You ".to_string()+verb+" the {y}"+&item.get_name()+"{x}.";
+		// The item's inventory slot is free now, so the old one fits
+		self.equip(item);
 		return result;
 	}
 
+	pub fn wield_item_by_name(&mut self, key: &String) -> String
+	{
+		return self.equip_item_by_name(key,ItemCategoryCode::Weapon,"wield");
+	}
+
+	pub fn wear_item_by_name(&mut self, key: &String) -> String
+	{
+		return self.equip_item_by_name(key,ItemCategoryCode::Armor,"wear");
+	}
+
+	// Move an equipped item back into the inventory
+	pub fn remove_item_by_name(&mut self, key: &String) -> String
+	{
+		let mut lower_case_key = key.clone();
+		lower_case_key.make_ascii_lowercase();
+		let matches = |item: &Option<Box<Item> >| -> bool
+			{
+				match item
+				{
+					Some(item) => { return item.name.to_ascii_lowercase().contains(&lower_case_key); },
+					None => { return false; }
+				}
+			};
+		let slot_code =
+			if matches(&self.weapon) { ItemCategoryCode::Weapon }
+			else if matches(&self.worn_armor) { ItemCategoryCode::Armor }
+			else { return "You aren't using that.".to_string(); };
+		if self.misc_items_slots == 0
+		{
+			return "You don't have room to carry it!".to_string();
+		}
+		let item = self.take_off(slot_code).unwrap();
+		let result = "You stop using the {y}".to_string()+&item.get_name()+"{x}.";
+		self.add_item(item,false);
+		return result;
+	}
+
+	// Take off whatever is equipped, one item at a time
+	pub fn fetch_equipped_item(&mut self) -> Option<Box<Item> >
+	{
+		let weapon = self.take_off(ItemCategoryCode::Weapon);
+		if weapon.is_some()
+		{
+			return weapon;
+		}
+		return self.take_off(ItemCategoryCode::Armor);
+	}
+
 	fn attribute_modifier(attribute: i16) -> i16
 	{
 		if attribute <= 1 { return -5; }
@@ -371,6 +576,26 @@ impl Mobile
 		return self.roll_skill(self.dexterity,self.stealth);
 	}
 
+	// Does this mobile spot the other one, hiding or sneaking?
+	pub fn notices(&self, other: &Mobile) -> bool
+	{
+		return self.roll_perception() > other.roll_stealth();
+	}
+
+	pub fn is_visible_to(&self, uuid: usize) -> bool
+	{
+		return !self.hidden || self.id == uuid || self.spotted_by.contains(&uuid);
+	}
+
+	// Come out of hiding. Returns true if the mobile was hidden.
+	pub fn stop_hiding(&mut self) -> bool
+	{
+		let was_hidden = self.hidden;
+		self.hidden = false;
+		self.spotted_by.clear();
+		return was_hidden;
+	}
+
 	pub fn roll_knowledge(&self) -> i16
 	{
 		return self.roll_skill(self.intelligence,self.knowledge);
@@ -449,16 +674,8 @@ impl Mobile
 			return None;
 		}
 		let mut item = self.inventory.remove(pos);
-		let slot_code = item.category_code.clone();
 		item.drop_item(self);
-		{
-			match slot_code
-			{
-				ItemCategoryCode::Misc => { self.misc_items_slots += 1; },
-				ItemCategoryCode::Weapon => { self.is_armed = false; self.unwield(); },
-				ItemCategoryCode::Armor => { self.armor -= item.armor_value; self.is_armored = false; },
-			}
-		}
+		self.misc_items_slots += 1;
 		return Some(item);
 	}
 
@@ -484,6 +701,12 @@ impl Mobile
 	}
 
 	pub fn fetch_item_by_name(&mut self, key: &String) -> Option<Box<Item> >
+	{
+		let pos = self.find_item_by_name(key)?;
+		return self.fetch_item_by_position(pos);
+	}
+
+	pub fn find_item_by_name(&self, key: &String) -> Option<usize>
 	{
 		let mut i = 0;
 		let mut lower_case_key = key.clone();
@@ -492,9 +715,9 @@ impl Mobile
 		{
 			let mut name = self.inventory[i].name.clone();
 			name.make_ascii_lowercase();
-    		if name.contains(key)
+    		if name.contains(&lower_case_key)
 			{
-				return self.fetch_item_by_position(i);
+				return Some(i);
 			}
 			i += 1;
 		}
@@ -515,28 +738,22 @@ impl Mobile
 		return None;
 	}
 
-	pub fn has_room_for_item(&mut self, item: &Box<Item>) -> bool
+	// Every item carried takes up a slot, whatever it is
+	pub fn has_room_for_item(&self) -> bool
 	{
-		match item.category_code
-		{
-			ItemCategoryCode::Misc => { return self.misc_items_slots > 0; },
-			ItemCategoryCode::Weapon => { return !self.is_armed; },
-			ItemCategoryCode::Armor => { return !self.is_armored; },
-		}
+		return self.misc_items_slots > 0;
+	}
+
+	// Room for several items at once, as when trading
+	pub fn has_room_for_items(&self, count: usize) -> bool
+	{
+		return count <= self.misc_items_slots.into();
 	}
 
 	pub fn add_item(&mut self, mut item: Box<Item>, take_xp: bool)
 	{
-		let slot_code = item.category_code.clone();
 		item.got_item(self,take_xp);
-		{
-			match slot_code
-			{
-				ItemCategoryCode::Misc => { self.misc_items_slots -= 1; },
-				ItemCategoryCode::Weapon => { self.is_armed = true; },
-				ItemCategoryCode::Armor => { self.is_armored = true; self.armor += item.armor_value; },
-			}
-		}
+		self.misc_items_slots = self.misc_items_slots.saturating_sub(1);
 		self.inventory.push(item);
 	}
 
@@ -583,12 +800,20 @@ impl Mobile
 				inventory: Vec::new(),
 				misc_items_slots: 10,
 				is_armed: false,
-				is_armored: false,
+				weapon: None,
+				worn_armor: None,
 				frequency: Mobile::routine_task(),
 				armor: 0,
 				wanders: false,
 				aggressive: false,
-				collects: false
+				collects: false,
+				fighting: BTreeSet::new(),
+				hidden: false,
+				spotted_by: BTreeSet::new(),
+				sneaking: false,
+				color: false,
+				prompt: DEFAULT_PROMPT.to_string(),
+				visited: BTreeSet::new()
 			});
 	}
 
@@ -702,8 +927,8 @@ impl Mobile
 		mobile.aggressive = true;
 		let weapon = Item::sword();
 		let armor = Item::leather_armor();
-		mobile.add_item(weapon,false);
-		mobile.add_item(armor,false);
+		mobile.equip(weapon);
+		mobile.equip(armor);
 		let treasure = Item::minor_treasure();
 		if treasure.is_some()
 		{
@@ -721,7 +946,7 @@ impl Mobile
 		mobile.wanders = true;
 		mobile.aggressive = true;
 		let weapon = Item::pointed_stick();
-		mobile.add_item(weapon,false);
+		mobile.equip(weapon);
 		let treasure = Item::minor_treasure();
 		if treasure.is_some()
 		{
@@ -738,7 +963,7 @@ impl Mobile
 		mobile.wanders = true;
 		mobile.aggressive = true;
 		let weapon = Item::stone_knife();
-		mobile.add_item(weapon,false);
+		mobile.equip(weapon);
 		let item = Item::shrunken_head();
 		mobile.add_item(item,false);
 		return mobile;
@@ -752,7 +977,7 @@ impl Mobile
 		mobile.wisdom = 8;
 		mobile.charisma = 13;
 		let weapon = Item::sword();
-		mobile.add_item(weapon,false);
+		mobile.equip(weapon);
 		return mobile;
 	}
 
@@ -766,7 +991,7 @@ impl Mobile
 		mobile.strength = 16;
 		mobile.wanders = true;
 		let weapon = Item::axe();
-		mobile.add_item(weapon,false);
+		mobile.equip(weapon);
 		return mobile;
 	}
 
@@ -781,8 +1006,8 @@ impl Mobile
 		mobile.frequency = Mobile::skilled_task();
 		let weapon = Item::axe();
 		let item = Item::chainmail();
-		mobile.add_item(weapon,false);
-		mobile.add_item(item,false);
+		mobile.equip(weapon);
+		mobile.equip(item);
 		let die = Dice { number: 1, die: 20 };
 		if die.roll() == 1
 		{
@@ -802,8 +1027,8 @@ impl Mobile
 		mobile.frequency = Mobile::skilled_task();
 		let weapon = Item::axe();
 		let item = Item::chainmail();
-		mobile.add_item(weapon,false);
-		mobile.add_item(item,false);
+		mobile.equip(weapon);
+		mobile.equip(item);
 		return mobile;
 	}
 
@@ -817,7 +1042,7 @@ impl Mobile
 		mobile.frequency = Mobile::skilled_task();
 		let weapon = Item::pick();
 		let item = Item::metal_ingot();
-		mobile.add_item(weapon,false);
+		mobile.equip(weapon);
 		mobile.add_item(item,false);
 		return mobile;
 	}
@@ -876,33 +1101,86 @@ mod mobile_unit_test
 		let protection = armor.armor_value;
 		let mut mobile = Mobile::new(&"goober".to_string());
 		mobile.add_item(armor, true);
+		// Carrying armor doesn't protect you
+		assert_eq!(mobile.armor,0);
+		assert_eq!(mobile.wear_item_by_name(&"Leather".to_string()),"You wear the {y}leather armor{x}.");
 		assert_eq!(mobile.armor,protection);
-		let armor = mobile.fetch_first_item();
+		assert!(mobile.inventory.is_empty());
+		assert_eq!(mobile.remove_item_by_name(&"armor".to_string()),"You stop using the {y}leather armor{x}.");
 		assert_eq!(mobile.armor,0);
+		let armor = mobile.fetch_first_item();
 		assert_eq!(armor.unwrap().armor_value,protection);
 	}
 
+	#[test]
+	fn wield_test()
+	{
+		let mut mobile = Mobile::new(&"goober".to_string());
+		let slots = mobile.misc_items_slots;
+		mobile.add_item(Item::sword(),true);
+		mobile.add_item(Item::axe(),true);
+		mobile.add_item(Item::rabbit_foot(),true);
+		assert_eq!(mobile.misc_items_slots,slots-3);
+		assert_eq!(mobile.wielded,"fist");
+		assert_eq!(mobile.wield_item_by_name(&"foot".to_string()),"You can't wield that.");
+		assert_eq!(mobile.wear_item_by_name(&"sword".to_string()),"You can't wear that.");
+		assert_eq!(mobile.wield_item_by_name(&"spoon".to_string()),"You aren't carrying that.");
+		mobile.wield_item_by_name(&"sword".to_string());
+		assert_eq!(mobile.wielded,"sword");
+		assert_eq!(mobile.damage_dice.die,8);
+		assert_eq!(mobile.misc_items_slots,slots-2);
+		// Wielding the axe puts the sword back in the pack
+		mobile.wield_item_by_name(&"axe".to_string());
+		assert_eq!(mobile.wielded,"axe");
+		assert_eq!(mobile.misc_items_slots,slots-2);
+		assert!(mobile.list_inventory().contains("sword"));
+		assert_eq!(mobile.list_equipment(),"wielded: {y}axe{x}\nworn: nothing\n");
+		// There must be room in the pack to take it off
+		mobile.misc_items_slots = 0;
+		assert_eq!(mobile.remove_item_by_name(&"axe".to_string()),"You don't have room to carry it!");
+		mobile.misc_items_slots = 1;
+		assert_eq!(mobile.remove_item_by_name(&"helmet".to_string()),"You aren't using that.");
+		mobile.remove_item_by_name(&"axe".to_string());
+		assert_eq!(mobile.wielded,"fist");
+		assert_eq!(mobile.damage_dice.die,2);
+		assert!(mobile.weapon.is_none());
+		// Monsters start with their gear equipped
+		let mut bandit = Mobile::bandit();
+		assert_eq!(bandit.wielded,"sword");
+		assert!(bandit.armor > 0);
+		assert!(bandit.fetch_equipped_item().is_some());
+		assert!(bandit.fetch_equipped_item().is_some());
+		assert!(bandit.fetch_equipped_item().is_none());
+		assert_eq!(bandit.armor,0);
+	}
+
 	#[test]
 	fn test_slots()
 	{
 		let mut foot = Item::rabbit_foot();
 		let mut mobile = Mobile::new_character(&"Jim".to_string());
-		while mobile.has_room_for_item(&foot)
+		while mobile.has_room_for_item()
 		{
 			mobile.add_item(foot,true);
 			foot = Item::rabbit_foot();
 		}
 		mobile.fetch_item_by_position(0);
-		assert!(mobile.has_room_for_item(&foot));
+		assert!(mobile.has_room_for_item());
 	}
 
 	#[test]
 	fn save_load_mobile()
 	{
-		let c1 = Mobile::new_character(&"Test".to_string());
-		c1.save_to_file();
+		let mut c1 = Mobile::new_character(&"Test".to_string());
+		c1.color = true;
+		c1.prompt = "%h, %x >".to_string();
+		c1.visited.insert((0,0));
+		c1.visited.insert((-3,12));
+		c1.equip(Item::sword());
+		c1.equip(Item::leather_armor());
+		c1.save_to_file(".");
 		let mut c2 = Mobile::new_character(&"Test".to_string());
-		assert!(c2.load_from_file());
+		assert!(c2.load_from_file("."));
 		assert_eq!(c1.strength,c2.strength);
 		assert_eq!(c1.intelligence,c2.intelligence);
 		assert_eq!(c1.wisdom,c2.wisdom);
@@ -917,7 +1195,14 @@ mod mobile_unit_test
 		assert_eq!(c1.leatherwork,c2.leatherwork);
 		assert_eq!(c1.metalwork,c2.metalwork);
 		assert_eq!(c1.woodcraft,c2.woodcraft);
+		assert_eq!(c1.color,c2.color);
+		assert_eq!(c1.prompt,c2.prompt);
+		assert_eq!(c1.visited,c2.visited);
+		assert_eq!(c2.weapon.as_ref().map(|item| item.name.clone()),Some("sword".to_string()));
+		assert_eq!(c2.wielded,"sword");
+		assert_eq!(c1.armor,c2.armor);
+		assert!(c2.worn_armor.is_some());
 		let mut c3 = Mobile::new_character(&"Lord Tom".to_string());
-		assert!(!c3.load_from_file());
+		assert!(!c3.load_from_file("."));
 	}
 }
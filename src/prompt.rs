@@ -0,0 +1,63 @@
+// The prompt shown to players after their output. Players set a
+// template in which these tokens are filled in:
+//   %h  damage taken and the most they can take
+//   %x  experience points
+//   %c  where they are on the map
+//   %t  the terrain they are in
+//   %%  a percent sign
+use crate::location::LocationTypeCode;
+use crate::mobile::Mobile;
+
+pub const DEFAULT_PROMPT: &str = ">>";
+
+// Longest template that we keep
+pub const MAX_PROMPT_LENGTH: usize = 80;
+
+// Fill in the tokens. A space always follows the prompt, as
+// templates lose their trailing spaces when they are typed.
+pub fn render(template: &str, mobile: &Mobile, x: i16, y: i16, location_type: LocationTypeCode) -> String
+{
+	let mut result = String::new();
+	let mut chars = template.chars();
+	while let Some(c) = chars.next()
+	{
+		if c != '%'
+		{
+			result.push(c);
+			continue;
+		}
+		match chars.next()
+		{
+			Some('h') => { result += &(mobile.damage.to_string()+"/"+&mobile.max_hit_points().to_string()); },
+			Some('x') => { result += &mobile.xp.to_string(); },
+			Some('c') => { result += &(x.to_string()+","+&y.to_string()); },
+			Some('t') => { result += location_type.name(); },
+			Some('%') => { result.push('%'); },
+			// Not a token, so leave it alone
+			Some(other) => { result.push('%'); result.push(other); },
+			None => { result.push('%'); }
+		}
+	}
+	result.push(' ');
+	return result;
+}
+
+#[cfg(test)]
+mod prompt_unit_test
+{
+	use super::*;
+
+	#[test]
+	fn render_test()
+	{
+		let mut mobile = Mobile::new_character(&"Jim".to_string());
+		mobile.damage = 2;
+		mobile.xp = 7;
+		let max = mobile.max_hit_points();
+		assert_eq!(render(DEFAULT_PROMPT,&mobile,0,0,LocationTypeCode::Town),">> ");
+		let hp = "2/".to_string()+&max.to_string();
+		assert_eq!(render("%h %x>",&mobile,0,0,LocationTypeCode::Town),hp+" 7> ");
+		assert_eq!(render("[%c %t] 100%%",&mobile,-1,3,LocationTypeCode::DeepWoods),"[-1,3 Deep Woods] 100% ");
+		assert_eq!(render("%q 5%",&mobile,0,0,LocationTypeCode::Town),"%q 5% ");
+	}
+}
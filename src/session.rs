@@ -0,0 +1,672 @@
+use crate::telnet::*;
+use crate::account::*;
+use crate::config::Config;
+use crate::limits::*;
+use crate::mobile::*;
+use crate::object::Object;
+use crate::uid;
+use std::collections::VecDeque;
+use std::io::{prelude::*,ErrorKind};
+use std::net::TcpStream;
+use std::sync::{Arc,mpsc::{self,Sender,TryRecvError}};
+use std::time::{Duration,Instant};
+
+// A bidirectional byte stream that a player connects through
+pub trait Transport: Read + Write + Send
+{
+	// Wait at most this long for input. None waits forever.
+	fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()>;
+	// Does the client speak telnet on this stream?
+	fn uses_telnet(&self) -> bool
+	{
+		return true;
+	}
+}
+
+impl Transport for TcpStream
+{
+	fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()>
+	{
+		return TcpStream::set_read_timeout(self,timeout);
+	}
+}
+
+// Messages sent from a session to the game
+pub enum ClientMessage
+{
+	// A character has logged in. Output for the session goes to the sender.
+	Login { session: usize, character: Box<Mobile>, output: Sender<ServerMessage> },
+	// A line of input from the player
+	Command { session: usize, line: String },
+	// The connection is gone
+	Disconnect { session: usize }
+}
+
+// Messages sent from the game to a session
+pub enum ServerMessage
+{
+	Output(String),
+	// A GMCP package and its JSON data
+	Gmcp(String),
+	// The prompt to show from now on
+	Prompt(String),
+	// Ask for a new password for the account, and for the old one first if need be
+	ChangePassword { name: String, check_old: bool },
+	Close
+}
+
+// Longest line that we accept. Anything more is dropped.
+const MAX_LINE_LENGTH: usize = 512;
+
+const BACKSPACE: u8 = 8;
+const DELETE: u8 = 127;
+
+// Splits the input into lines. A line ends with CR, LF or CR LF.
+// Backspace and delete remove the last character and other control
+// characters are dropped.
+pub struct LineBuffer
+{
+	line: Vec<u8>,
+	// Was the last byte a carriage return?
+	after_cr: bool
+}
+
+impl LineBuffer
+{
+	pub fn new() -> LineBuffer
+	{
+		return LineBuffer { line: Vec::new(), after_cr: false };
+	}
+
+	// Add the data and return any lines that it completes
+	pub fn push(&mut self, data: &[u8]) -> Vec<String>
+	{
+		let mut lines = Vec::new();
+		for byte in data.iter()
+		{
+			let byte = *byte;
+			let after_cr = self.after_cr;
+			self.after_cr = byte == b'\r';
+			match byte
+			{
+				b'\r' | b'\n' =>
+					{
+						// The LF of a CR LF pair
+						if byte == b'\n' && after_cr
+						{
+							continue;
+						}
+						let line = std::mem::take(&mut self.line);
+						lines.push(String::from_utf8_lossy(&line).to_string());
+					},
+				BACKSPACE | DELETE =>
+					{
+						// Remove a whole character, not just its last byte
+						while let Some(last) = self.line.pop()
+						{
+							if last & 0xC0 != 0x80
+							{
+								break;
+							}
+						}
+					},
+				b'\t' => { self.add(b' '); },
+				_ =>
+					{
+						if byte >= 32
+						{
+							self.add(byte);
+						}
+					}
+			}
+		}
+		return lines;
+	}
+
+	fn add(&mut self, byte: u8)
+	{
+		if self.line.len() < MAX_LINE_LENGTH
+		{
+			self.line.push(byte);
+		}
+	}
+}
+
+// A connection to a player. Everything read from a telnet connection
+// passes through the protocol layer so that only data reaches the game.
+pub struct Session
+{
+	stream: Box<dyn Transport>,
+	telnet: Telnet,
+	use_telnet: bool,
+	input: LineBuffer,
+	// Lines that have been received but not yet consumed
+	lines: VecDeque<String>,
+	// Waiting for a line gives up at this time
+	deadline: Option<Instant>
+}
+
+impl Session
+{
+	pub fn new(stream: Box<dyn Transport>) -> Session
+	{
+		let use_telnet = stream.uses_telnet();
+		let mut session = Session { stream: stream, telnet: Telnet::new(), use_telnet: use_telnet, input: LineBuffer::new(), lines: VecDeque::new(), deadline: None };
+		if use_telnet
+		{
+			session.telnet.start_negotiation();
+			session.send_reply();
+		}
+		return session;
+	}
+
+	pub fn set_read_timeout(&mut self, timeout: Option<Duration>)
+	{
+		let _ = self.stream.set_read_timeout(timeout);
+	}
+
+	// Make read_line give up at this time. None waits forever.
+	pub fn set_deadline(&mut self, deadline: Option<Instant>)
+	{
+		self.deadline = deadline;
+	}
+
+	// Stop the client from echoing what the user types
+	pub fn hide_input(&mut self, hide: bool)
+	{
+		if self.use_telnet
+		{
+			self.telnet.set_echo(hide);
+			self.send_reply();
+		}
+	}
+
+	// Send structured data if the client asked for it
+	pub fn send_gmcp(&mut self, message: &str)
+	{
+		if self.use_telnet && self.telnet.gmcp_enabled()
+		{
+			self.telnet.send_subnegotiation(OPT_GMCP,message.as_bytes());
+			self.send_reply();
+		}
+	}
+
+	fn send_reply(&mut self)
+	{
+		let reply = self.telnet.take_reply();
+		if !reply.is_empty()
+		{
+			let _ = self.stream.write_all(&reply);
+			let _ = self.stream.flush();
+		}
+	}
+
+	// Read whatever data is available. An empty result means that
+	// only protocol commands arrived or the read timed out.
+	pub fn read(&mut self) -> std::io::Result<Vec<u8> >
+	{
+		let mut buf = vec![0;128];
+		let n = match self.stream.read(&mut buf)
+		{
+			Err(e) =>
+				{
+					match e.kind()
+					{
+						ErrorKind::WouldBlock | ErrorKind::TimedOut => { 0 },
+						_ => { return Err(e); }
+					}
+				}
+			Ok(m) =>
+				{
+					if m == 0 { return Err(std::io::Error::from(ErrorKind::UnexpectedEof)); }
+					m
+				}
+		};
+		buf.truncate(n);
+		if self.use_telnet
+		{
+			let data = self.telnet.receive(&buf);
+			self.send_reply();
+			return Ok(data);
+		}
+		return Ok(buf);
+	}
+
+	// Read whatever data is available and return the complete lines
+	pub fn read_lines(&mut self) -> std::io::Result<Vec<String> >
+	{
+		let data = self.read()?;
+		let mut lines: Vec<String> = self.lines.drain(..).collect();
+		lines.extend(self.input.push(&data));
+		return Ok(lines);
+	}
+
+	// Block until a complete line has been read
+	pub fn read_line(&mut self) -> Option<String>
+	{
+		loop
+		{
+			let line = self.lines.pop_front();
+			if line.is_some()
+			{
+				return line;
+			}
+			match self.deadline
+			{
+				Some(deadline) =>
+					{
+						let now = Instant::now();
+						if now >= deadline
+						{
+							return None;
+						}
+						self.set_read_timeout(Some(deadline-now));
+					},
+				None => { () }
+			}
+			match self.read_lines()
+			{
+				Err(_e) => { return None; },
+				Ok(lines) => { self.lines.extend(lines); }
+			}
+		}
+	}
+
+	pub fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()>
+	{
+		// Data bytes that look like IAC must be escaped
+		if self.use_telnet && buf.contains(&IAC)
+		{
+			let mut escaped = Vec::with_capacity(buf.len()+1);
+			for byte in buf.iter()
+			{
+				if *byte == IAC { escaped.push(IAC); }
+				escaped.push(*byte);
+			}
+			return self.stream.write_all(&escaped);
+		}
+		return self.stream.write_all(buf);
+	}
+
+	pub fn flush(&mut self) -> std::io::Result<()>
+	{
+		return self.stream.flush();
+	}
+}
+
+// Number of tries to get the password right before being disconnected
+const MAX_PASSWORD_ATTEMPTS : u8 = 3;
+
+// Returns None if the connection fails, as do the other login questions
+fn ask(stream: &mut Session, question: &[u8]) -> Option<String>
+{
+	stream.write_all(question).ok()?;
+	stream.flush().ok()?;
+	let line = stream.read_line();
+	match line
+	{
+		Some(line) => { return Some(line.trim().to_string()); },
+		None => { return None; }
+	}
+}
+
+fn ask_password(stream: &mut Session, question: &[u8]) -> Option<String>
+{
+	stream.hide_input(true);
+	let password = ask(stream,question);
+	stream.hide_input(false);
+	// The client did not echo the end of the line
+	stream.write_all(b"\n").ok()?;
+	return password;
+}
+
+fn check_password(stream: &mut Session, account: &Account) -> bool
+{
+	for _ in 0..MAX_PASSWORD_ATTEMPTS
+	{
+		let password = match ask_password(stream,b"Password: ")
+		{
+			None => { return false; },
+			Some(password) => { password }
+		};
+		if account.verify(&password)
+		{
+			return true;
+		}
+		if stream.write_all(b"Wrong password.\n").is_err()
+		{
+			return false;
+		}
+	}
+	let _ = stream.write_all(b"Too many failed attempts!\n");
+	let _ = stream.flush();
+	return false;
+}
+
+fn create_account(stream: &mut Session, name: &String) -> Option<Account>
+{
+	loop
+	{
+		let password = ask_password(stream,b"Choose a password: ")?;
+		if password.is_empty()
+		{
+			continue;
+		}
+		let confirm = ask_password(stream,b"Repeat the password: ")?;
+		if password == confirm
+		{
+			return Some(Account::new(name,&password));
+		}
+		stream.write_all(b"The passwords do not match.\n").ok()?;
+	}
+}
+
+// Set a new password for the account. Players have to give the old
+// one first, but admins do not. None means that the player went away.
+fn change_password(stream: &mut Session, save_directory: &str, name: &String, check_old: bool) -> Option<String>
+{
+	if check_old
+	{
+		let account = match Account::load(save_directory,name)
+		{
+			Some(account) => { account },
+			None => { return Some("You have no account!\n".to_string()); }
+		};
+		let password = ask_password(stream,b"Old password: ")?;
+		if !account.verify(&password)
+		{
+			return Some("Wrong password.\n".to_string());
+		}
+	}
+	let account = create_account(stream,name)?;
+	account.save(save_directory);
+	return Some("Password changed.\n".to_string());
+}
+
+fn load_character(stream: &mut Session, save_directory: &str) -> Option<Box<Mobile> >
+{
+	stream.write_all(b"Welcome!\n").ok()?;
+	let mut name = ask(stream,b"What is your name? ")?;
+	while !Account::valid_name(&name)
+	{
+		stream.write_all(b"Names must be 2 to 20 letters.\n").ok()?;
+		name = ask(stream,b"What is your name? ")?;
+	}
+	let account = Account::load(save_directory,&name);
+	let mut character = Mobile::new_character(&name);
+	let exists = character.load_from_file(save_directory);
+	match account
+	{
+		Some(ref account) =>
+			{
+				if !check_password(stream,account)
+				{
+					return None;
+				}
+			},
+		None =>
+			{
+				// Characters saved before there were accounts could be
+				// taken by anyone who knew the name
+				if exists
+				{
+					let _ = stream.write_all(b"This character has no password yet. Ask an admin to set one.\n");
+					let _ = stream.flush();
+					return None;
+				}
+			}
+	}
+	if !exists
+	{
+		loop
+		{
+			stream.write_all(character.complete_description().as_bytes()).ok()?;
+			let line = ask(stream,b"Keep this character (y/n)? ")?;
+			if line.contains(&"y")
+			{
+				break;
+			}
+			character = Mobile::new_character(&name);
+		}
+	}
+	if account.is_none()
+	{
+		let account = create_account(stream,&name)?;
+		account.save(save_directory);
+	}
+	// After this the game saves the character from time to time
+	if !exists
+	{
+		character.save_to_file(save_directory);
+	}
+	return Some(character);
+}
+
+// Write to the player, starting on a new line if the last output did not end one
+fn write_output(stream: &mut Session, text: &str, last_output_char: &mut char) -> std::io::Result<()>
+{
+	if *last_output_char != '\n'
+	{
+		stream.write_all(b"\n")?;
+	}
+	stream.write_all(text.as_bytes())?;
+	stream.flush()?;
+	*last_output_char = text.chars().last().unwrap_or(*last_output_char);
+	return Ok(());
+}
+
+// Turn a telnet client away, saying why
+pub fn refuse_connection(mut stream: TcpStream, message: &str)
+{
+	let _ = stream.set_write_timeout(Some(REFUSAL_TIMEOUT));
+	let _ = stream.write_all(message.as_bytes());
+}
+
+// Get input from the user and pass it to the game. Show the user
+// whatever the game sends back.
+pub fn handle_connection(stream: Box<dyn Transport>, game: Sender<ClientMessage>, config: Arc<Config>)
+{
+	let session = uid::new();
+	let mut print_prompt = true;
+	let mut stream = Session::new(stream);
+	let login_time = Duration::from_secs(config.login_seconds.into());
+	let sub_tick = Duration::from_millis(config.sub_tick.into());
+	// Someone who never finishes logging in must not hold the place forever
+	stream.set_deadline(Some(Instant::now()+login_time));
+	let character =
+		match load_character(&mut stream,&config.save_directory)
+		{
+			Some(character) => { character },
+			None => { return; }
+		};
+	stream.set_deadline(None);
+	let (output, from_game) = mpsc::channel();
+	if game.send(ClientMessage::Login { session: session, character: character, output: output }).is_err()
+	{
+		return;
+	}
+	stream.set_read_timeout(Some(sub_tick));
+	let mut last_output_char = '\n';
+	let mut prompt = ">> ".to_string();
+	let mut limiter = CommandRateLimiter::new(config.commands_per_second);
+	let mut flooded = false;
+	loop
+	{
+		if print_prompt && write_output(&mut stream,&prompt,&mut last_output_char).is_err()
+		{
+			break;
+		}
+		print_prompt = false;
+		let lines = match stream.read_lines()
+		{
+			Err(_e) => { break; },
+			Ok(lines) => { lines }
+		};
+		for line in lines
+		{
+			last_output_char = '\n';
+			match limiter.check(Instant::now())
+			{
+				RateCheck::Allowed => { () },
+				RateCheck::Dropped => { continue; },
+				RateCheck::Warn =>
+					{
+						let _ = write_output(&mut stream,"You are sending commands too quickly. Slow down or you will be disconnected.\n",&mut last_output_char);
+						print_prompt = true;
+						continue;
+					},
+				RateCheck::Disconnect =>
+					{
+						let _ = write_output(&mut stream,"You have been disconnected for sending commands too quickly.\n",&mut last_output_char);
+						flooded = true;
+						break;
+					}
+			}
+			if game.send(ClientMessage::Command { session: session, line: line }).is_err()
+			{
+				return;
+			}
+		}
+		if flooded
+		{
+			break;
+		}
+		// Display whatever the game has sent us
+		let mut closed = false;
+		let mut lost = false;
+		loop
+		{
+			match from_game.try_recv()
+			{
+				Ok(ServerMessage::Output(message_for_user)) =>
+					{
+						print_prompt = true;
+						if message_for_user.is_empty()
+						{
+							continue;
+						}
+						if write_output(&mut stream,&message_for_user,&mut last_output_char).is_err()
+						{
+							lost = true;
+							break;
+						}
+					},
+				Ok(ServerMessage::Gmcp(message)) => { stream.send_gmcp(&message); },
+				Ok(ServerMessage::Prompt(new_prompt)) => { prompt = new_prompt; },
+				Ok(ServerMessage::ChangePassword { name, check_old }) =>
+					{
+						print_prompt = true;
+						stream.set_deadline(Some(Instant::now()+login_time));
+						let result = change_password(&mut stream,&config.save_directory,&name,check_old);
+						stream.set_deadline(None);
+						stream.set_read_timeout(Some(sub_tick));
+						// The prompts end their own lines
+						last_output_char = '\n';
+						let message_for_user = result.unwrap_or("Your password has not been changed.\n".to_string());
+						if write_output(&mut stream,&message_for_user,&mut last_output_char).is_err()
+						{
+							lost = true;
+							break;
+						}
+					},
+				Ok(ServerMessage::Close) => { closed = true; break; },
+				Err(TryRecvError::Empty) => { break; },
+				Err(TryRecvError::Disconnected) => { closed = true; break; }
+			}
+		}
+		if closed
+		{
+			return;
+		}
+		if lost
+		{
+			break;
+		}
+	}
+	// The game keeps the character for a while in case the player comes back
+	let _ = game.send(ClientMessage::Disconnect { session: session });
+}
+
+#[cfg(test)]
+mod session_unit_test
+{
+	use super::*;
+
+	// A client that sends a name and then hangs up
+	struct BrokenTransport
+	{
+		input: Vec<u8>,
+		writes_left: usize
+	}
+
+	impl Read for BrokenTransport
+	{
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize>
+		{
+			let n = std::cmp::min(buf.len(),self.input.len());
+			buf[..n].copy_from_slice(&self.input[..n]);
+			self.input.drain(..n);
+			return Ok(n);
+		}
+	}
+
+	impl Write for BrokenTransport
+	{
+		fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>
+		{
+			if self.writes_left == 0
+			{
+				return Err(std::io::Error::from(ErrorKind::BrokenPipe));
+			}
+			self.writes_left -= 1;
+			return Ok(buf.len());
+		}
+
+		fn flush(&mut self) -> std::io::Result<()>
+		{
+			return Ok(());
+		}
+	}
+
+	impl Transport for BrokenTransport
+	{
+		fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> std::io::Result<()>
+		{
+			return Ok(());
+		}
+
+		fn uses_telnet(&self) -> bool
+		{
+			return false;
+		}
+	}
+
+	#[test]
+	fn broken_connection_test()
+	{
+		// Losing the connection at any point of the login gives up quietly
+		for writes_left in 0..4
+		{
+			let transport = BrokenTransport { input: b"Brokenlink\n".to_vec(), writes_left: writes_left };
+			let mut stream = Session::new(Box::new(transport));
+			assert!(load_character(&mut stream,".").is_none());
+		}
+	}
+
+	#[test]
+	fn line_buffer_test()
+	{
+		let mut input = LineBuffer::new();
+		assert!(input.push(b"loo").is_empty());
+		assert_eq!(input.push(b"k\r"),vec!["look"]);
+		// The LF of CR LF does not make an empty line
+		assert_eq!(input.push(b"\nn\ne\r\n\r\n"),vec!["n","e",""]);
+		assert_eq!(input.push(b"gexy\x08\x7ft\tall\x1b\n"),vec!["get all"]);
+		// Backspace removes a whole character
+		assert_eq!(input.push("caf\u{e9}\x08e\n".as_bytes()),vec!["cafe"]);
+		let long = vec![b'x';MAX_LINE_LENGTH+10];
+		let lines = input.push(&long);
+		assert!(lines.is_empty());
+		assert_eq!(input.push(b"\n")[0].len(),MAX_LINE_LENGTH);
+	}
+}
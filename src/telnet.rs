@@ -0,0 +1,421 @@
+// Telnet protocol negotiation. Incoming bytes are passed through the
+// protocol state machine, which strips IAC sequences from the data and
+// queues any replies that must be sent back to the client.
+
+// Telnet commands
+pub const SE: u8 = 240;
+pub const SB: u8 = 250;
+pub const WILL: u8 = 251;
+pub const WONT: u8 = 252;
+pub const DO: u8 = 253;
+pub const DONT: u8 = 254;
+pub const IAC: u8 = 255;
+
+// Telnet options
+pub const OPT_ECHO: u8 = 1;
+pub const OPT_SGA: u8 = 3;
+pub const OPT_TTYPE: u8 = 24;
+pub const OPT_NAWS: u8 = 31;
+// Generic MUD Communication Protocol
+pub const OPT_GMCP: u8 = 201;
+
+// Terminal type subnegotiation commands
+const TTYPE_IS: u8 = 0;
+const TTYPE_SEND: u8 = 1;
+
+// Window size to assume until the client tells us otherwise
+const DEFAULT_WIDTH: u16 = 80;
+const DEFAULT_HEIGHT: u16 = 24;
+
+#[derive(Copy,Clone,PartialEq)]
+enum ParseState
+{
+	Data,
+	// Saw a carriage return, drop a following NUL
+	CarriageReturn,
+	Iac,
+	// Saw IAC followed by WILL, WONT, DO, or DONT
+	Negotiate(u8),
+	Subnegotiation,
+	SubnegotiationIac
+}
+
+pub struct Telnet
+{
+	state: ParseState,
+	// Contents of the subnegotiation being received
+	subnegotiation: Vec<u8>,
+	// Bytes that must be sent to the client
+	reply: Vec<u8>,
+	// Options that we have enabled on our side
+	local_enabled: [bool;256],
+	// Options that the client has enabled on its side
+	remote_enabled: [bool;256],
+	// Options for which we are waiting on an answer
+	local_requested: [bool;256],
+	remote_requested: [bool;256],
+	// What we last asked for on our side. This can change while we wait.
+	local_wanted: [bool;256],
+	pub window_width: u16,
+	pub window_height: u16,
+	pub terminal_type: Option<String>
+}
+
+impl Telnet
+{
+	pub fn new() -> Telnet
+	{
+		return Telnet
+		{
+			state: ParseState::Data,
+			subnegotiation: Vec::new(),
+			reply: Vec::new(),
+			local_enabled: [false;256],
+			remote_enabled: [false;256],
+			local_requested: [false;256],
+			remote_requested: [false;256],
+			local_wanted: [false;256],
+			window_width: DEFAULT_WIDTH,
+			window_height: DEFAULT_HEIGHT,
+			terminal_type: None
+		};
+	}
+
+	// Offer the options we want when the client connects
+	pub fn start_negotiation(&mut self)
+	{
+		self.request_local(OPT_SGA,true);
+		self.request_local(OPT_GMCP,true);
+		self.request_remote(OPT_NAWS,true);
+		self.request_remote(OPT_TTYPE,true);
+	}
+
+	// Take the bytes that need to be sent to the client
+	pub fn take_reply(&mut self) -> Vec<u8>
+	{
+		return std::mem::take(&mut self.reply);
+	}
+
+	// When the server echoes, the client stops echoing what is typed.
+	// Turn this on to hide passwords.
+	pub fn set_echo(&mut self, server_echoes: bool)
+	{
+		self.request_local(OPT_ECHO,server_echoes);
+	}
+
+	// Has the client agreed to receive GMCP messages?
+	pub fn gmcp_enabled(&self) -> bool
+	{
+		return self.local_enabled[OPT_GMCP as usize];
+	}
+
+	// Queue a subnegotiation for the client. IAC in the data is escaped.
+	pub fn send_subnegotiation(&mut self, option: u8, data: &[u8])
+	{
+		self.reply.extend_from_slice(&[IAC,SB,option]);
+		for byte in data.iter()
+		{
+			if *byte == IAC { self.reply.push(IAC); }
+			self.reply.push(*byte);
+		}
+		self.reply.extend_from_slice(&[IAC,SE]);
+	}
+
+	// Strip telnet commands from the input and return the data that remains
+	pub fn receive(&mut self, input: &[u8]) -> Vec<u8>
+	{
+		let mut data = Vec::new();
+		for byte in input.iter()
+		{
+			let byte = *byte;
+			match self.state
+			{
+				ParseState::Data | ParseState::CarriageReturn =>
+					{
+						let after_cr = self.state == ParseState::CarriageReturn;
+						self.state = ParseState::Data;
+						if byte == IAC { self.state = ParseState::Iac; }
+						else if byte == 0 && after_cr { () }
+						else
+						{
+							if byte == b'\r' { self.state = ParseState::CarriageReturn; }
+							data.push(byte);
+						}
+					},
+				ParseState::Iac =>
+					{
+						match byte
+						{
+							IAC => { data.push(IAC); self.state = ParseState::Data; },
+							WILL | WONT | DO | DONT => { self.state = ParseState::Negotiate(byte); },
+							SB => { self.subnegotiation.clear(); self.state = ParseState::Subnegotiation; },
+							// Other commands (NOP, GA, AYT, etc.) are ignored
+							_ => { self.state = ParseState::Data; }
+						}
+					},
+				ParseState::Negotiate(command) =>
+					{
+						self.negotiate(command,byte);
+						self.state = ParseState::Data;
+					},
+				ParseState::Subnegotiation =>
+					{
+						if byte == IAC { self.state = ParseState::SubnegotiationIac; }
+						else { self.subnegotiation.push(byte); }
+					},
+				ParseState::SubnegotiationIac =>
+					{
+						match byte
+						{
+							IAC => { self.subnegotiation.push(IAC); self.state = ParseState::Subnegotiation; },
+							SE => { self.end_subnegotiation(); self.state = ParseState::Data; },
+							// Malformed, so drop the subnegotiation
+							_ => { self.subnegotiation.clear(); self.state = ParseState::Data; }
+						}
+					}
+			}
+		}
+		return data;
+	}
+
+	fn send_command(&mut self, command: u8, option: u8)
+	{
+		self.reply.extend_from_slice(&[IAC,command,option]);
+	}
+
+	// Ask to turn an option on or off on our side
+	fn request_local(&mut self, option: u8, enable: bool)
+	{
+		let index = option as usize;
+		self.local_wanted[index] = enable;
+		if self.local_enabled[index] == enable || self.local_requested[index]
+		{
+			return;
+		}
+		self.local_requested[index] = true;
+		self.send_command(if enable { WILL } else { WONT },option);
+	}
+
+	// Ask the client to turn an option on or off on its side
+	fn request_remote(&mut self, option: u8, enable: bool)
+	{
+		let index = option as usize;
+		if self.remote_enabled[index] == enable || self.remote_requested[index]
+		{
+			return;
+		}
+		self.remote_requested[index] = true;
+		self.send_command(if enable { DO } else { DONT },option);
+	}
+
+	fn supports_local(option: u8) -> bool
+	{
+		return option == OPT_ECHO || option == OPT_SGA || option == OPT_GMCP;
+	}
+
+	fn supports_remote(option: u8) -> bool
+	{
+		return option == OPT_NAWS || option == OPT_TTYPE;
+	}
+
+	fn negotiate(&mut self, command: u8, option: u8)
+	{
+		let index = option as usize;
+		match command
+		{
+			DO =>
+				{
+					let requested = self.local_requested[index];
+					self.local_requested[index] = false;
+					if self.local_enabled[index] { return; }
+					if Telnet::supports_local(option)
+					{
+						self.local_enabled[index] = true;
+						if !requested { self.send_command(WILL,option); }
+					}
+					else
+					{
+						self.send_command(WONT,option);
+					}
+					// We may have changed our mind while waiting for the answer
+					if requested && !self.local_wanted[index] { self.request_local(option,false); }
+				},
+			DONT =>
+				{
+					let requested = self.local_requested[index];
+					self.local_requested[index] = false;
+					if self.local_enabled[index]
+					{
+						self.local_enabled[index] = false;
+						if !requested { self.send_command(WONT,option); }
+						else if self.local_wanted[index] { self.request_local(option,true); }
+					}
+				},
+			WILL =>
+				{
+					let requested = self.remote_requested[index];
+					self.remote_requested[index] = false;
+					if self.remote_enabled[index] { return; }
+					if Telnet::supports_remote(option)
+					{
+						self.remote_enabled[index] = true;
+						if !requested { self.send_command(DO,option); }
+						if option == OPT_TTYPE
+						{
+							self.reply.extend_from_slice(&[IAC,SB,OPT_TTYPE,TTYPE_SEND,IAC,SE]);
+						}
+					}
+					else
+					{
+						self.send_command(DONT,option);
+					}
+				},
+			WONT =>
+				{
+					let requested = self.remote_requested[index];
+					self.remote_requested[index] = false;
+					if self.remote_enabled[index]
+					{
+						self.remote_enabled[index] = false;
+						if !requested { self.send_command(DONT,option); }
+					}
+				},
+			_ => { return; }
+		}
+	}
+
+	fn end_subnegotiation(&mut self)
+	{
+		let sb = std::mem::take(&mut self.subnegotiation);
+		if sb.is_empty()
+		{
+			return;
+		}
+		match sb[0]
+		{
+			OPT_NAWS =>
+				{
+					if sb.len() == 5
+					{
+						let width = u16::from_be_bytes([sb[1],sb[2]]);
+						let height = u16::from_be_bytes([sb[3],sb[4]]);
+						// Zero means the client does not know
+						if width > 0 { self.window_width = width; }
+						if height > 0 { self.window_height = height; }
+					}
+				},
+			OPT_TTYPE =>
+				{
+					if sb.len() > 1 && sb[1] == TTYPE_IS
+					{
+						self.terminal_type = Some(String::from_utf8_lossy(&sb[2..]).to_string());
+					}
+				},
+			_ => { return; }
+		}
+	}
+}
+
+#[cfg(test)]
+mod telnet_unit_test
+{
+	use super::*;
+
+	#[test]
+	fn strip_commands_test()
+	{
+		let mut telnet = Telnet::new();
+		let data = telnet.receive(&[b'l',IAC,241,b'o',IAC,IAC,b'k',b'\r',0,b'\n']);
+		assert_eq!(data,vec![b'l',b'o',IAC,b'k',b'\r',b'\n']);
+		// A command split across two reads
+		let mut data = telnet.receive(&[b'a',IAC]);
+		data.extend(telnet.receive(&[DO]));
+		data.extend(telnet.receive(&[99,b'b']));
+		assert_eq!(data,vec![b'a',b'b']);
+		assert_eq!(telnet.take_reply(),vec![IAC,WONT,99]);
+	}
+
+	#[test]
+	fn negotiate_test()
+	{
+		let mut telnet = Telnet::new();
+		telnet.start_negotiation();
+		assert_eq!(telnet.take_reply(),vec![IAC,WILL,OPT_SGA,IAC,WILL,OPT_GMCP,IAC,DO,OPT_NAWS,IAC,DO,OPT_TTYPE]);
+		// Agreement to what we asked for gets no reply except the request for the terminal type
+		telnet.receive(&[IAC,DO,OPT_SGA,IAC,WILL,OPT_NAWS]);
+		assert!(telnet.take_reply().is_empty());
+		assert!(telnet.local_enabled[OPT_SGA as usize]);
+		assert!(telnet.remote_enabled[OPT_NAWS as usize]);
+		telnet.receive(&[IAC,WILL,OPT_TTYPE]);
+		assert_eq!(telnet.take_reply(),vec![IAC,SB,OPT_TTYPE,TTYPE_SEND,IAC,SE]);
+		// Options we don't know are refused
+		telnet.receive(&[IAC,WILL,42]);
+		assert_eq!(telnet.take_reply(),vec![IAC,DONT,42]);
+		// Echo for hiding passwords
+		telnet.set_echo(true);
+		assert_eq!(telnet.take_reply(),vec![IAC,WILL,OPT_ECHO]);
+		telnet.receive(&[IAC,DO,OPT_ECHO]);
+		assert!(telnet.local_enabled[OPT_ECHO as usize]);
+		telnet.set_echo(false);
+		assert_eq!(telnet.take_reply(),vec![IAC,WONT,OPT_ECHO]);
+		telnet.receive(&[IAC,DONT,OPT_ECHO]);
+		assert!(!telnet.local_enabled[OPT_ECHO as usize]);
+		assert!(telnet.take_reply().is_empty());
+	}
+
+	#[test]
+	fn late_echo_test()
+	{
+		let mut telnet = Telnet::new();
+		// The password is typed before the client answers the request to echo
+		telnet.set_echo(true);
+		assert_eq!(telnet.take_reply(),vec![IAC,WILL,OPT_ECHO]);
+		telnet.set_echo(false);
+		assert!(telnet.take_reply().is_empty());
+		telnet.receive(&[IAC,DO,OPT_ECHO]);
+		assert_eq!(telnet.take_reply(),vec![IAC,WONT,OPT_ECHO]);
+		telnet.receive(&[IAC,DONT,OPT_ECHO]);
+		assert!(!telnet.local_enabled[OPT_ECHO as usize]);
+		assert!(telnet.take_reply().is_empty());
+		// And the other way round
+		telnet.set_echo(true);
+		telnet.receive(&[IAC,DO,OPT_ECHO]);
+		telnet.take_reply();
+		telnet.set_echo(false);
+		telnet.set_echo(true);
+		assert_eq!(telnet.take_reply(),vec![IAC,WONT,OPT_ECHO]);
+		telnet.receive(&[IAC,DONT,OPT_ECHO]);
+		assert_eq!(telnet.take_reply(),vec![IAC,WILL,OPT_ECHO]);
+		telnet.receive(&[IAC,DO,OPT_ECHO]);
+		assert!(telnet.local_enabled[OPT_ECHO as usize]);
+		assert!(telnet.take_reply().is_empty());
+	}
+
+	#[test]
+	fn subnegotiation_test()
+	{
+		let mut telnet = Telnet::new();
+		assert_eq!(telnet.window_width,DEFAULT_WIDTH);
+		let data = telnet.receive(&[b'x',IAC,SB,OPT_NAWS,0,132,0,IAC,IAC,IAC,SE,b'y']);
+		assert_eq!(data,vec![b'x',b'y']);
+		assert_eq!(telnet.window_width,132);
+		assert_eq!(telnet.window_height,255);
+		telnet.receive(&[IAC,SB,OPT_TTYPE,TTYPE_IS,b'x',b't',b'e',b'r',b'm',IAC,SE]);
+		assert_eq!(telnet.terminal_type,Some("xterm".to_string()));
+	}
+
+	#[test]
+	fn gmcp_test()
+	{
+		let mut telnet = Telnet::new();
+		telnet.start_negotiation();
+		telnet.take_reply();
+		assert!(!telnet.gmcp_enabled());
+		// GMCP from the client, such as Core.Hello, is ignored
+		let data = telnet.receive(&[IAC,DO,OPT_GMCP,IAC,SB,OPT_GMCP,b'C',b'o',b'r',b'e',IAC,SE]);
+		assert!(data.is_empty());
+		assert!(telnet.gmcp_enabled());
+		telnet.send_subnegotiation(OPT_GMCP,&[b'a',IAC,b'b']);
+		assert_eq!(telnet.take_reply(),vec![IAC,SB,OPT_GMCP,b'a',IAC,IAC,b'b',IAC,SE]);
+	}
+}
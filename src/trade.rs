@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+// One side of a trade: who it is with and what is on the table
+struct TradeSide
+{
+	partner: usize,
+	offer: Vec<String>,
+	accepted: bool
+}
+
+// Trades between players. A trade is open once each player has asked
+// to trade with the other. Items are only offered by name here; they
+// stay in the inventories until the swap.
+pub struct TradeList
+{
+	sides: BTreeMap<usize,TradeSide>
+}
+
+impl TradeList
+{
+	pub fn new() -> TradeList
+	{
+		return TradeList { sides: BTreeMap::new() };
+	}
+
+	// Ask to trade with someone. Returns true if they had already asked,
+	// so the trade is now open. Any other trade is called off first.
+	pub fn propose(&mut self, uuid: usize, partner: usize) -> bool
+	{
+		self.cancel(uuid);
+		self.sides.insert(uuid,TradeSide { partner: partner, offer: Vec::new(), accepted: false });
+		return self.is_open(uuid);
+	}
+
+	pub fn partner(&self, uuid: usize) -> Option<usize>
+	{
+		return self.sides.get(&uuid).map(|side| side.partner);
+	}
+
+	pub fn is_open(&self, uuid: usize) -> bool
+	{
+		match self.partner(uuid)
+		{
+			Some(partner) => { return self.partner(partner) == Some(uuid); },
+			None => { return false; }
+		}
+	}
+
+	pub fn offer(&self, uuid: usize) -> Vec<String>
+	{
+		match self.sides.get(&uuid)
+		{
+			Some(side) => { return side.offer.clone(); },
+			None => { return Vec::new(); }
+		}
+	}
+
+	// Put an item on the table. Changing the offer means that both
+	// players have to accept again.
+	pub fn add_offer(&mut self, uuid: usize, item_name: String)
+	{
+		if !self.is_open(uuid)
+		{
+			return;
+		}
+		let partner = self.partner(uuid).unwrap();
+		for id in [uuid,partner]
+		{
+			self.sides.get_mut(&id).unwrap().accepted = false;
+		}
+		self.sides.get_mut(&uuid).unwrap().offer.push(item_name);
+	}
+
+	// Accept the trade as it stands. Returns true once both have.
+	pub fn accept(&mut self, uuid: usize) -> bool
+	{
+		if !self.is_open(uuid)
+		{
+			return false;
+		}
+		let partner = self.partner(uuid).unwrap();
+		self.sides.get_mut(&uuid).unwrap().accepted = true;
+		return self.sides.get(&partner).unwrap().accepted;
+	}
+
+	// Take back an acceptance, for example when the swap can't be made
+	pub fn withdraw(&mut self, uuid: usize)
+	{
+		match self.sides.get_mut(&uuid)
+		{
+			Some(side) => { side.accepted = false; },
+			None => { () }
+		}
+	}
+
+	// Call off whatever trade this player is in. Returns the partner if
+	// the trade was open.
+	pub fn cancel(&mut self, uuid: usize) -> Option<usize>
+	{
+		let was_open = self.is_open(uuid);
+		let side = self.sides.remove(&uuid)?;
+		if was_open
+		{
+			self.sides.remove(&side.partner);
+			return Some(side.partner);
+		}
+		return None;
+	}
+}
+
+#[cfg(test)]
+mod trade_unit_test
+{
+	use super::*;
+
+	#[test]
+	fn trade_test()
+	{
+		let mut trades = TradeList::new();
+		assert!(!trades.propose(1,2));
+		assert!(!trades.is_open(1));
+		trades.add_offer(1,"sword".to_string());
+		assert!(trades.offer(1).is_empty());
+		assert!(trades.propose(2,1));
+		assert!(trades.is_open(1));
+		trades.add_offer(1,"sword".to_string());
+		assert!(!trades.accept(2));
+		assert!(trades.accept(1));
+		// A new offer needs to be accepted again
+		trades.add_offer(2,"rabbit foot".to_string());
+		assert!(!trades.accept(1));
+		assert_eq!(trades.offer(1),vec!["sword".to_string()]);
+		assert_eq!(trades.cancel(2),Some(1));
+		assert!(trades.partner(1).is_none());
+		assert!(trades.cancel(1).is_none());
+	}
+}
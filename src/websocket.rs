@@ -0,0 +1,438 @@
+// WebSocket connections for browser clients. The handshake and framing
+// follow RFC 6455. Each text message from the browser is one line of
+// input, and everything the game flushes is sent as one text message.
+use crate::session::Transport;
+use crate::limits::REFUSAL_TIMEOUT;
+use base64::Engine;
+use sha1::{Digest,Sha1};
+use std::io::{ErrorKind,Read,Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// Frame opcodes
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+// Status code sent when closing because the client broke the protocol
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+
+// Limits on what a client may send us
+const MAX_HANDSHAKE_SIZE: usize = 8192;
+const MAX_MESSAGE_SIZE: usize = 65536;
+// Clients that take longer than this to open the connection are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Frame
+{
+	pub fin: bool,
+	pub opcode: u8,
+	// Clients must mask every frame that they send
+	pub masked: bool,
+	pub payload: Vec<u8>
+}
+
+pub struct WebSocket
+{
+	stream: TcpStream,
+	// Raw bytes that do not yet make a complete frame
+	received: Vec<u8>,
+	// A message split across continuation frames
+	fragments: Vec<u8>,
+	// Message contents that have not been read
+	data: Vec<u8>,
+	// Output waiting for a flush
+	output: Vec<u8>,
+	closed: bool
+}
+
+impl Transport for WebSocket
+{
+	fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()>
+	{
+		return self.stream.set_read_timeout(timeout);
+	}
+
+	fn uses_telnet(&self) -> bool
+	{
+		return false;
+	}
+}
+
+impl WebSocket
+{
+	// Perform the server side of the opening handshake
+	pub fn accept(mut stream: TcpStream) -> std::io::Result<WebSocket>
+	{
+		stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+		let mut request = Vec::new();
+		let mut buf = vec![0;1024];
+		let header_end;
+		loop
+		{
+			let n = stream.read(&mut buf)?;
+			if n == 0
+			{
+				return Err(std::io::Error::from(ErrorKind::UnexpectedEof));
+			}
+			request.extend_from_slice(&buf[..n]);
+			let end = request.windows(4).position(|w| w == b"\r\n\r\n");
+			if let Some(end) = end
+			{
+				header_end = end+4;
+				break;
+			}
+			if request.len() > MAX_HANDSHAKE_SIZE
+			{
+				return Err(std::io::Error::from(ErrorKind::InvalidData));
+			}
+		}
+		let headers = String::from_utf8_lossy(&request[..header_end]).to_string();
+		let mut key = None;
+		for line in headers.lines()
+		{
+			let mut parts = line.splitn(2,':');
+			let name = parts.next().unwrap_or("").trim();
+			let value = parts.next().unwrap_or("").trim();
+			if name.eq_ignore_ascii_case("Sec-WebSocket-Key")
+			{
+				key = Some(value.to_string());
+			}
+		}
+		match key
+		{
+			Some(key) =>
+				{
+					let response = "HTTP/1.1 101 Switching Protocols\r\n".to_string()+
+						"Upgrade: websocket\r\n"+
+						"Connection: Upgrade\r\n"+
+						"Sec-WebSocket-Accept: "+&WebSocket::accept_key(&key)+"\r\n\r\n";
+					stream.write_all(response.as_bytes())?;
+					stream.flush()?;
+				},
+			None =>
+				{
+					stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+					stream.flush()?;
+					return Err(std::io::Error::from(ErrorKind::InvalidData));
+				}
+		}
+		return Ok(WebSocket
+			{
+				stream: stream,
+				received: request.split_off(header_end),
+				fragments: Vec::new(),
+				data: Vec::new(),
+				output: Vec::new(),
+				closed: false
+			});
+	}
+
+	// Turn a browser away before the handshake, saying why
+	pub fn refuse(mut stream: TcpStream, message: &str)
+	{
+		let response = "HTTP/1.1 503 Service Unavailable\r\n".to_string()+
+			"Content-Type: text/plain\r\n"+
+			"Content-Length: "+&message.len().to_string()+"\r\n"+
+			"Connection: close\r\n\r\n"+message;
+		let _ = stream.set_write_timeout(Some(REFUSAL_TIMEOUT));
+		let _ = stream.write_all(response.as_bytes());
+	}
+
+	pub fn accept_key(key: &str) -> String
+	{
+		let digest = Sha1::digest((key.to_string()+WEBSOCKET_GUID).as_bytes());
+		return base64::engine::general_purpose::STANDARD.encode(digest);
+	}
+
+	// Try to take one frame from the front of the buffer. Returns the
+	// frame and the number of bytes it used, or None if it is incomplete.
+	pub fn decode_frame(buf: &[u8]) -> std::io::Result<Option<(Frame,usize)> >
+	{
+		if buf.len() < 2
+		{
+			return Ok(None);
+		}
+		let fin = buf[0] & 0x80 != 0;
+		let opcode = buf[0] & 0x0F;
+		let masked = buf[1] & 0x80 != 0;
+		let mut length = (buf[1] & 0x7F) as usize;
+		let mut pos = 2;
+		if length == 126
+		{
+			if buf.len() < pos+2 { return Ok(None); }
+			length = u16::from_be_bytes([buf[2],buf[3]]) as usize;
+			pos += 2;
+		}
+		else if length == 127
+		{
+			if buf.len() < pos+8 { return Ok(None); }
+			let mut bytes = [0;8];
+			bytes.copy_from_slice(&buf[2..10]);
+			length = u64::from_be_bytes(bytes) as usize;
+			pos += 8;
+		}
+		if length > MAX_MESSAGE_SIZE
+		{
+			return Err(std::io::Error::from(ErrorKind::InvalidData));
+		}
+		let mut mask = [0;4];
+		if masked
+		{
+			if buf.len() < pos+4 { return Ok(None); }
+			mask.copy_from_slice(&buf[pos..pos+4]);
+			pos += 4;
+		}
+		if buf.len() < pos+length
+		{
+			return Ok(None);
+		}
+		let mut payload = buf[pos..pos+length].to_vec();
+		if masked
+		{
+			for (i,byte) in payload.iter_mut().enumerate()
+			{
+				*byte ^= mask[i % 4];
+			}
+		}
+		return Ok(Some((Frame { fin: fin, opcode: opcode, masked: masked, payload: payload },pos+length)));
+	}
+
+	// Servers send unmasked frames
+	pub fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8>
+	{
+		let mut frame = vec![0x80 | opcode];
+		if payload.len() < 126
+		{
+			frame.push(payload.len() as u8);
+		}
+		else if payload.len() <= u16::MAX as usize
+		{
+			frame.push(126);
+			frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+		}
+		else
+		{
+			frame.push(127);
+			frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+		}
+		frame.extend_from_slice(payload);
+		return frame;
+	}
+
+	// Turn the complete frames we have received into data
+	fn decode_frames(&mut self) -> std::io::Result<()>
+	{
+		loop
+		{
+			let frame = WebSocket::decode_frame(&self.received)?;
+			let (frame, used) = match frame
+			{
+				Some(frame) => { frame },
+				None => { return Ok(()); }
+			};
+			self.received.drain(..used);
+			if !frame.masked
+			{
+				let _ = self.stream.write_all(&WebSocket::encode_frame(OPCODE_CLOSE,&CLOSE_PROTOCOL_ERROR.to_be_bytes()));
+				self.closed = true;
+				return Err(std::io::Error::from(ErrorKind::InvalidData));
+			}
+			match frame.opcode
+			{
+				OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION =>
+					{
+						self.fragments.extend(frame.payload);
+						if self.fragments.len() > MAX_MESSAGE_SIZE
+						{
+							return Err(std::io::Error::from(ErrorKind::InvalidData));
+						}
+						if frame.fin
+						{
+							// Each message is a line of input
+							let mut message = std::mem::take(&mut self.fragments);
+							if message.last() != Some(&b'\n')
+							{
+								message.push(b'\n');
+							}
+							self.data.extend(message);
+						}
+					},
+				OPCODE_PING =>
+					{
+						self.stream.write_all(&WebSocket::encode_frame(OPCODE_PONG,&frame.payload))?;
+					},
+				OPCODE_CLOSE =>
+					{
+						let _ = self.stream.write_all(&WebSocket::encode_frame(OPCODE_CLOSE,&frame.payload));
+						self.closed = true;
+						return Ok(());
+					},
+				_ => { () }
+			}
+		}
+	}
+}
+
+impl Read for WebSocket
+{
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize>
+	{
+		// Frames may have arrived together with the handshake
+		self.decode_frames()?;
+		while self.data.is_empty()
+		{
+			if self.closed
+			{
+				return Ok(0);
+			}
+			let mut raw = vec![0;1024];
+			let n = self.stream.read(&mut raw)?;
+			if n == 0
+			{
+				return Ok(0);
+			}
+			self.received.extend_from_slice(&raw[..n]);
+			self.decode_frames()?;
+		}
+		let n = std::cmp::min(buf.len(),self.data.len());
+		buf[..n].copy_from_slice(&self.data[..n]);
+		self.data.drain(..n);
+		return Ok(n);
+	}
+}
+
+impl Write for WebSocket
+{
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>
+	{
+		self.output.extend_from_slice(buf);
+		return Ok(buf.len());
+	}
+
+	fn flush(&mut self) -> std::io::Result<()>
+	{
+		if self.output.is_empty()
+		{
+			return Ok(());
+		}
+		let output = std::mem::take(&mut self.output);
+		let opcode = if std::str::from_utf8(&output).is_ok() { OPCODE_TEXT } else { OPCODE_BINARY };
+		self.stream.write_all(&WebSocket::encode_frame(opcode,&output))?;
+		return self.stream.flush();
+	}
+}
+
+#[cfg(test)]
+mod websocket_unit_test
+{
+	use super::*;
+	use std::net::TcpListener;
+	use std::thread;
+
+	#[test]
+	fn accept_key_test()
+	{
+		// The example from RFC 6455
+		assert_eq!(WebSocket::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),"s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+	}
+
+	#[test]
+	fn frame_test()
+	{
+		// A masked "Hello" from RFC 6455
+		let buf = [0x81,0x85,0x37,0xfa,0x21,0x3d,0x7f,0x9f,0x4d,0x51,0x58];
+		assert!(WebSocket::decode_frame(&buf[..6]).unwrap().is_none());
+		let (frame, used) = WebSocket::decode_frame(&buf).unwrap().unwrap();
+		assert!(frame.fin);
+		assert!(frame.masked);
+		assert_eq!(frame.opcode,OPCODE_TEXT);
+		assert_eq!(frame.payload,b"Hello".to_vec());
+		assert_eq!(used,buf.len());
+		assert_eq!(WebSocket::encode_frame(OPCODE_TEXT,b"Hello"),vec![0x81,0x05,b'H',b'e',b'l',b'l',b'o']);
+		let long = vec![b'x';300];
+		let encoded = WebSocket::encode_frame(OPCODE_TEXT,&long);
+		assert_eq!(&encoded[..4],&[0x81,126,0x01,0x2c]);
+		let (frame, _) = WebSocket::decode_frame(&encoded).unwrap().unwrap();
+		assert!(!frame.masked);
+		assert_eq!(frame.payload,long);
+	}
+
+	#[test]
+	fn connection_test()
+	{
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap();
+		let client = thread::spawn(move ||
+			{
+				let mut stream = TcpStream::connect(address).unwrap();
+				stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+					Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+					Sec-WebSocket-Version: 13\r\n\r\n").unwrap();
+				// Send "Hello" split across two masked fragments
+				stream.write_all(&[0x01,0x83,0x37,0xfa,0x21,0x3d,0x7f,0x9f,0x4d]).unwrap();
+				stream.write_all(&[0x80,0x82,0x37,0xfa,0x21,0x3d,0x5b,0x95]).unwrap();
+				let mut response = Vec::new();
+				let mut buf = vec![0;1024];
+				while !response.ends_with(b"Hi!")
+				{
+					let n = stream.read(&mut buf).unwrap();
+					assert!(n > 0);
+					response.extend_from_slice(&buf[..n]);
+				}
+				return response;
+			});
+		let (stream, _) = listener.accept().unwrap();
+		let mut websocket = WebSocket::accept(stream).unwrap();
+		let mut line = String::new();
+		let mut buf = vec![0;16];
+		while !line.ends_with('\n')
+		{
+			let n = websocket.read(&mut buf).unwrap();
+			line += &String::from_utf8_lossy(&buf[..n]);
+		}
+		assert_eq!(line,"Hello\n");
+		websocket.write_all(b"Hi").unwrap();
+		websocket.write_all(b"!").unwrap();
+		websocket.flush().unwrap();
+		let response = client.join().unwrap();
+		let handshake = String::from_utf8_lossy(&response[..response.len()-5]).to_string();
+		assert!(handshake.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+		assert!(handshake.ends_with("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"));
+		assert!(response.ends_with(&[0x81,0x03,b'H',b'i',b'!']));
+	}
+
+	#[test]
+	fn unmasked_frame_test()
+	{
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap();
+		let client = thread::spawn(move ||
+			{
+				let mut stream = TcpStream::connect(address).unwrap();
+				stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+					Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+					Sec-WebSocket-Version: 13\r\n\r\n").unwrap();
+				// "Hi" without a mask
+				stream.write_all(&[0x81,0x02,b'H',b'i']).unwrap();
+				let mut response = Vec::new();
+				let mut buf = vec![0;1024];
+				while !response.ends_with(&[0x88,0x02,0x03,0xea])
+				{
+					let n = stream.read(&mut buf).unwrap();
+					assert!(n > 0);
+					response.extend_from_slice(&buf[..n]);
+				}
+			});
+		let (stream, _) = listener.accept().unwrap();
+		let mut websocket = WebSocket::accept(stream).unwrap();
+		let mut buf = vec![0;16];
+		assert_eq!(websocket.read(&mut buf).unwrap_err().kind(),ErrorKind::InvalidData);
+		client.join().unwrap();
+	}
+}
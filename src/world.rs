@@ -1,16 +1,38 @@
 use crate::location::*;
 use crate::message::MessageList;
+use crate::trade::TradeList;
 use crate::map::*;
 use crate::mobile::*;
 use crate::items::*;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap,BTreeSet};
+use std::path::Path;
+use std::time::Instant;
+
+// The explored map and the items on the ground are saved here
+const WORLD_FILE_NAME: &str = "world.csv";
+
+// A player who is to be asked for a new password for an account
+pub struct PasswordRequest
+{
+	pub uuid: usize,
+	pub name: String,
+	// Does the player have to give the old password first?
+	pub check_old: bool
+}
 
 pub struct WorldState
 {
 	pub message_list: MessageList,
+	pub trades: TradeList,
 	map: Map,
 	mobile_uuid_to_location: BTreeMap<usize,(i16,i16)>,
-	stash: BTreeMap<usize,(Box<Mobile>,i16,i16)>
+	stash: BTreeMap<usize,(Box<Mobile>,i16,i16)>,
+	// Where characters are saved
+	pub save_directory: String,
+	// When the server will shut down, if it is going to
+	pub shutdown_at: Option<Instant>,
+	// Passwords are typed to the session, not given as commands
+	pub password_requests: Vec<PasswordRequest>
 }
 
 impl WorldState
@@ -22,7 +44,28 @@ impl WorldState
 			stash: BTreeMap::new(),
 			map: Map::new(),
 			message_list: MessageList::new(),
+			trades: TradeList::new(),
 			mobile_uuid_to_location: BTreeMap::new(),
+			save_directory: ".".to_string(),
+			shutdown_at: None,
+			password_requests: Vec::new()
+		}
+	}
+
+	pub fn save_to_file(&self) -> bool
+	{
+		let file_name = Path::new(&self.save_directory).join(WORLD_FILE_NAME);
+		return self.map.save_to_file(&file_name).is_ok();
+	}
+
+	// Replace the map with the one that was saved
+	pub fn load_from_file(&mut self) -> bool
+	{
+		let file_name = Path::new(&self.save_directory).join(WORLD_FILE_NAME);
+		match Map::load_from_file(&file_name)
+		{
+			Some(map) => { self.map = map; return true; },
+			None => { return false; }
 		}
 	}
 
@@ -33,11 +76,18 @@ impl WorldState
 		return population as f32 / area as f32;
 	}
 
+	// Take a player out of the world. The character is saved as it goes.
 	pub fn stash_mobile(&mut self, uuid: usize)
 	{
 		let position = self.find_mobile_location(uuid).unwrap();
 		let mobile = self.fetch_mobile(uuid).unwrap();
+		mobile.save_to_file(&self.save_directory);
 		let id = mobile.get_id();
+		let partner = self.trades.cancel(id);
+		if partner.is_some()
+		{
+			self.message_list.post_for_target(mobile.name.clone()+" has left. The trade is off.",partner.unwrap());
+		}
 		let tuple = (mobile,position.0,position.1);
 		self.stash.insert(id,tuple);
 	}
@@ -97,20 +147,45 @@ impl WorldState
 		}
 	}
 
-	pub fn fetch_mobile_by_name(&mut self, x: i16, y: i16, key: &String) -> Option<Box<Mobile> >
+	pub fn get_mobile_name(&mut self, uuid: usize) -> Option<String>
+	{
+		let position = self.find_mobile_location(uuid)?;
+		let mobile = self.fetch_mobile(uuid)?;
+		let name = mobile.name.clone();
+		self.add_mobile(mobile,position.0,position.1);
+		return Some(name);
+	}
+
+	// Is this mobile still fighting that one? They must both be in the
+	// same place.
+	pub fn is_fighting(&mut self, uuid: usize, opponent: usize) -> bool
+	{
+		let position = self.find_mobile_location(uuid);
+		if position.is_none() || position != self.find_mobile_location(opponent)
+		{
+			return false;
+		}
+		let position = position.unwrap();
+		let mobile = self.fetch_mobile(uuid).unwrap();
+		let fighting = mobile.fighting.contains(&opponent);
+		self.add_mobile(mobile,position.0,position.1);
+		return fighting;
+	}
+
+	pub fn fetch_mobile_by_name(&mut self, x: i16, y: i16, key: &String, viewer: usize) -> Option<Box<Mobile> >
 	{
 		let mut location = self.map.fetch(x,y);
-		let mobile = location.fetch_mobile_by_name(key);
+		let mobile = location.fetch_mobile_by_name(key,viewer);
 		self.map.replace(location);
 		return mobile;
 	}
 
 	// Find and return a mobile by name. This removes it from the world and it
 	// must be added back to the world when you are done with it.
-	pub fn get_mobile_id_by_name(&mut self, x: i16, y: i16, key: &String) -> Option<usize>
+	pub fn get_mobile_id_by_name(&mut self, x: i16, y: i16, key: &String, viewer: usize) -> Option<usize>
 	{
 		let mut location = self.map.fetch(x,y);
-		let mobile = location.fetch_mobile_by_name(key);
+		let mobile = location.fetch_mobile_by_name(key,viewer);
 		match mobile
 		{
 			Some(mobile) =>
@@ -134,10 +209,10 @@ impl WorldState
 		self.map.replace(location);
 	}
 
-	pub fn fetch_item_by_name(&mut self, x: i16, y: i16, key: &String) -> Option<Box<Item> >
+	pub fn fetch_item_by_name(&mut self, x: i16, y: i16, key: &String, viewer: usize) -> Option<Box<Item> >
 	{
 		let mut location = self.map.fetch(x,y);
-		let item = location.fetch_item_by_name(key);
+		let item = location.fetch_item_by_name(key,viewer);
 		self.map.replace(location);
 		return item;	
 	}
@@ -150,9 +225,58 @@ impl WorldState
 		return item;	
 	}
 
-	pub fn get_location_description(&mut self, x: i16, y: i16) -> String
+	// What the viewer sees at x,y
+	pub fn get_location_description(&mut self, x: i16, y: i16, viewer: usize) -> String
+	{
+		return self.map.get_location_description(x,y,viewer);
+	}
+
+	// Where the exit in that direction from x,y goes, if the viewer knows of it
+	pub fn find_exit(&mut self, x: i16, y: i16, direction: &str, viewer: usize) -> Option<(i16,i16)>
+	{
+		let location = self.map.fetch(x,y);
+		let result = location.find_exit(direction,viewer);
+		self.map.replace(location);
+		return result;
+	}
+
+	// Search x,y for hidden things
+	pub fn search(&mut self, x: i16, y: i16, searcher: &Mobile) -> Vec<String>
+	{
+		let mut location = self.map.fetch(x,y);
+		let result = location.search(searcher);
+		self.map.replace(location);
+		return result;
+	}
+
+	// The mobile at x,y hides from those who don't notice
+	pub fn hide_mobile(&mut self, x: i16, y: i16, uuid: usize)
+	{
+		let mut location = self.map.fetch(x,y);
+		location.hide_mobile(uuid);
+		self.map.replace(location);
+	}
+
+	// The mobile has just arrived at x,y. It may spot those hiding there.
+	pub fn look_for_hidden(&mut self, x: i16, y: i16, uuid: usize)
+	{
+		let mut location = self.map.fetch(x,y);
+		location.look_for_hidden(uuid);
+		self.map.replace(location);
+	}
+
+	// Those at x,y who notice the sneak
+	pub fn noticed_by(&mut self, x: i16, y: i16, sneak: &Mobile) -> Vec<usize>
+	{
+		let location = self.map.fetch(x,y);
+		let result = location.noticed_by(sneak);
+		self.map.replace(location);
+		return result;
+	}
+
+	pub fn draw_map_window(&self, x: i16, y: i16, radius: i16, visited: &BTreeSet<(i16,i16)>) -> String
 	{
-		return self.map.get_location_description(x,y);
+		return self.map.draw_window(x,y,radius,visited);
 	}
 
 	pub fn get_location_type(&self, x: i16, y: i16) -> LocationTypeCode